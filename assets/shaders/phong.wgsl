@@ -0,0 +1,49 @@
+// A phong material, with parallax mapping when wrapped in `Parallax`, see the `phong` example.
+#import bevy_pbr::mesh_view_bindings
+#import bevy_mod_paramap::parallax_extension
+
+struct PhongMaterial {
+    color: vec4<f32>,
+    shininess: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> material: PhongMaterial;
+@group(1) @binding(1)
+var base_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var base_sampler: sampler;
+@group(1) @binding(3)
+var normal_map: texture_2d<f32>;
+@group(1) @binding(4)
+var normal_sampler: sampler;
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    #import bevy_pbr::mesh_vertex_output
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let uv = parallax_extension_uv(in.world_position, in.world_normal, in.world_tangent, in.uv);
+    let base_color = material.color * textureSample(base_texture, base_sampler, uv);
+
+    let N = normalize(in.world_normal);
+    let T = normalize(in.world_tangent.xyz - N * dot(in.world_tangent.xyz, N));
+    let B = cross(N, T) * in.world_tangent.w;
+    let tangent_normal = textureSample(normal_map, normal_sampler, uv).rgb * 2.0 - 1.0;
+    let normal = normalize(mat3x3<f32>(T, B, N) * tangent_normal);
+
+    let V = normalize(view.world_position.xyz - in.world_position.xyz);
+    var color = lights.ambient_color.rgb * base_color.rgb;
+    for (var i = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        let light = lights.directional_lights[i];
+        let L = light.direction_to_light;
+        let diffuse = max(dot(normal, L), 0.0);
+        let R = reflect(-L, normal);
+        let specular = pow(max(dot(R, V), 0.0), material.shininess);
+        color += light.color.rgb * (diffuse * base_color.rgb + specular);
+    }
+    return vec4<f32>(color, base_color.a);
+}
@@ -33,6 +33,8 @@ fn main() {
     .add_system(spin_cube)
     .add_system(handle_camera)
     .add_system(close_on_esc);
+    #[cfg(feature = "inspector")]
+    app.add_plugin(ParallaxInspectorPlugin);
 
     app.run();
 }
@@ -93,7 +95,6 @@ fn handle_camera(
 
 fn setup(
     mut cmd: Commands,
-    mut mats: ResMut<Assets<ParallaxMaterial>>,
     mut std_mats: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
     assets: Res<AssetServer>,
@@ -144,20 +145,12 @@ fn setup(
         ..default()
     });
     // Cube
-    let mut cube: Mesh = shape::Cube { size: 1.0 }.into();
-    cube.generate_tangents().unwrap();
+    // NOTE: `ParallaxMaterialPlugin` generates the tangents the cube mesh lacks.
     cmd.spawn(MaterialMeshBundle {
-        mesh: meshes.add(cube),
-        material: mats.add(ParallaxMaterial {
-            perceptual_roughness: 0.5,
-            base_color_texture: Some(assets.load("cube/paramap_color.jpg")),
-            normal_map_texture: assets.load("cube/paramap_normal.jpg"),
-            height_map: assets.load("cube/paramap_bump.jpg"),
-            height_depth: 0.1,
-            algorithm: ParallaxAlgo::ReliefMapping,
-            max_height_layers: 64.0,
-            ..default()
-        }),
+        mesh: meshes.add(shape::Cube { size: 1.0 }.into()),
+        // NOTE: The material is described in a file, edit it while the example
+        // runs to see the changes.
+        material: assets.load::<ParallaxMaterial, _>("cube/cube.paramap.ron"),
         ..default()
     })
     .insert(Spin);
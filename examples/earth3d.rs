@@ -5,7 +5,7 @@ use bevy::{
     core_pipeline::bloom::BloomSettings,
     input::mouse::{MouseMotion, MouseWheel},
     prelude::*,
-    render::{camera::Projection, render_resource::TextureFormat},
+    render::camera::Projection,
     window::{close_on_esc, WindowPlugin, WindowResolution},
 };
 use bevy_mod_paramap::*;
@@ -42,14 +42,14 @@ fn main() {
         brightness: 0.01,
     })
     .insert_resource(ClearColor(Color::BLACK))
-    .insert_resource(Normal(None))
     .add_startup_system(setup)
     .add_system(pan_orbit_camera)
-    .add_system(update_normal)
     .add_system(spin)
     .add_system(close_on_esc);
 
     app.register_type::<Spin>();
+    #[cfg(feature = "inspector")]
+    app.add_plugin(ParallaxInspectorPlugin);
 
     app.run();
 }
@@ -66,52 +66,18 @@ fn spin(time: Res<Time>, mut query: Query<(&mut Transform, &Spin)>) {
     }
 }
 
-/// Store handle of the earth normal to later modify its format
-/// in [`update_normal`].
-#[derive(Resource)]
-struct Normal(Option<Handle<Image>>);
-
-/// Work around the fact that the default bevy image loader sets the
-/// normal's format to something incompatible with normal shaders.
-/// The format must be one of the `TextureFormat` ending in `*Unorm`.
-///
-/// In this function, we wait until the image is loaded, immediately
-/// change its format and never run the core logic afterward.
-///
-/// Without proper format, it looks like the light source moves as the
-/// earth move, and there is major glitchy artifacts on the poles.
-fn update_normal(
-    mut already_ran: Local<bool>,
-    mut images: ResMut<Assets<Image>>,
-    normal: Res<Normal>,
-) {
-    if *already_ran {
-        return;
-    }
-    if let Some(normal) = normal.0.as_ref() {
-        if let Some(mut image) = images.get_mut(normal) {
-            image.texture_descriptor.format = TextureFormat::Rgba8Unorm;
-            *already_ran = true;
-        }
-    }
-}
-
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ParallaxMaterial>>,
-    mut normal: ResMut<Normal>,
     assets: Res<AssetServer>,
 ) {
     use bevy::math::EulerRot::XYZ;
-    let normal_handle = assets.load(NORMAL_MAP);
-    normal.0 = Some(normal_handle.clone());
-    let mut sphere: Mesh = shape::UVSphere::default().into();
-    sphere.generate_tangents().unwrap();
     commands
         .spawn(MaterialMeshBundle {
             transform: Transform::from_rotation(Quat::from_euler(XYZ, -TAU / 4.0, 0.0, TAU / 2.0)),
-            mesh: meshes.add(sphere),
+            // NOTE: `ParallaxMaterialPlugin` generates the tangents the sphere mesh lacks.
+            mesh: meshes.add(shape::UVSphere::default().into()),
             material: materials.add(ParallaxMaterial {
                 // reduce roughness set in the "earth/metallic_roughness.png" file
                 perceptual_roughness: 0.75,
@@ -124,16 +90,24 @@ fn setup(
                 // the nighttime visuals. See README for source.
                 emissive_texture: Some(assets.load(EMI_MAP)),
                 // The normal map generated from "earth/elevation_surface.png" using GIMP's
-                // Filters -> Generic -> Normal Map filter.
-                normal_map_texture: normal_handle,
+                // Filters -> Generic -> Normal Map filter. It can also be generated with
+                // `bevy_mod_paramap::bake::normal_map_from_height_map`.
+                // NOTE: `ParallaxMaterialPlugin` sets a linear format on the normal map,
+                // bevy's image loader picks an sRGB format, which breaks the lighting.
+                normal_map_texture: assets.load(NORMAL_MAP),
                 // See README for source.
                 height_map: assets.load(HEIGHT_MAP),
                 // Set the water to have a low roughness, while surface has high roughness.
                 metallic_roughness_texture: Some(assets.load(ROUGH_MAP)),
                 // How "deep" to displace stuff
                 height_depth: 0.01,
-                // Use the quality algo, for show.
-                algorithm: ParallaxAlgo::ReliefMapping,
+                // Use the quality algo, for show, unless on the web, where
+                // only the cheapest algo runs smoothly.
+                algorithm: if cfg!(target_arch = "wasm32") {
+                    ParallaxAlgo::OffsetLimitedParallaxMapping
+                } else {
+                    ParallaxAlgo::ReliefMapping
+                },
                 // This is an unreasonably high value, but since we expect to inspect up close
                 // the surface of the texture, we need to set the max_height_layers pretty high.
                 max_height_layers: 128.0,
@@ -269,7 +243,7 @@ fn pan_orbit_camera(
             any = true;
             pan_orbit.radius -= scroll * pan_orbit.radius * 0.2;
             // dont allow zoom to go bellow earth surface
-            pan_orbit.radius = pan_orbit.radius.max(1.1).min(30.0);
+            pan_orbit.radius = pan_orbit.radius.clamp(1.1, 30.0);
         }
 
         if any {
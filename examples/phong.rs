@@ -0,0 +1,157 @@
+//! Parallax mapping on a custom phong material and on bevy's `StandardMaterial`,
+//! see `Parallax`.
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{AsBindGroup, ShaderRef, TextureFormat},
+    window::close_on_esc,
+};
+use bevy_mod_paramap::*;
+
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(ParallaxMaterialPlugin)
+        .add_plugin(ParallaxPlugin::<PhongMaterial>::default())
+        .add_plugin(ParallaxPlugin::<StandardMaterial>::default())
+        .insert_resource(AmbientLight {
+            color: Color::WHITE,
+            brightness: 0.1,
+        })
+        .add_startup_system(setup)
+        .add_system(linearize_data_textures)
+        .add_system(spin_cube)
+        .add_system(close_on_esc)
+        .run();
+}
+
+/// A phong material, unaware of parallax mapping, apart from its shader
+/// calling `parallax_extension_uv`.
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "f690fdae-d598-45ab-8225-97e2a3f056e0"]
+struct PhongMaterial {
+    #[uniform(0)]
+    color: Color,
+    #[uniform(0)]
+    shininess: f32,
+    #[texture(1)]
+    #[sampler(2)]
+    base_texture: Handle<Image>,
+    #[texture(3)]
+    #[sampler(4)]
+    normal_map: Handle<Image>,
+}
+impl Material for PhongMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/phong.wgsl".into()
+    }
+}
+
+/// Bevy loads images as sRGB, but the normal and height maps store data.
+///
+/// `ParallaxMaterialPlugin` only does this for `ParallaxMaterial` textures.
+fn linearize_data_textures(
+    mut events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    phong_materials: Res<Assets<Parallax<PhongMaterial>>>,
+    standard_materials: Res<Assets<Parallax<StandardMaterial>>>,
+) {
+    for event in &mut events {
+        let AssetEvent::Created { handle } = event else {
+            continue;
+        };
+        let is_phong_data = |(_, material): (_, &Parallax<PhongMaterial>)| {
+            material.base.normal_map == *handle || material.height_map == *handle
+        };
+        let is_standard_data = |(_, material): (_, &Parallax<StandardMaterial>)| {
+            material.base.normal_map_texture.as_ref() == Some(handle)
+                || material.height_map == *handle
+        };
+        if !phong_materials.iter().any(is_phong_data)
+            && !standard_materials.iter().any(is_standard_data)
+        {
+            continue;
+        }
+        if let Some(image) = images.get_mut(handle) {
+            if image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb {
+                image.texture_descriptor.format = TextureFormat::Rgba8Unorm;
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+struct Spin;
+
+fn spin_cube(time: Res<Time>, mut query: Query<&mut Transform, With<Spin>>) {
+    for mut transform in &mut query {
+        transform.rotate_local_y(0.3 * time.delta_seconds());
+        transform.rotate_local_x(0.2 * time.delta_seconds());
+    }
+}
+
+fn setup(
+    mut cmd: Commands,
+    mut phong_materials: ResMut<Assets<Parallax<PhongMaterial>>>,
+    mut standard_materials: ResMut<Assets<Parallax<StandardMaterial>>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    assets: Res<AssetServer>,
+) {
+    cmd.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(2.5, 2.5, 2.5).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+    cmd.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(1.0, 2.0, 0.5).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+
+    // NOTE: unlike with `ParallaxMaterial`, tangents are not generated for us.
+    let mut cube = Mesh::from(shape::Cube { size: 1.0 });
+    cube.generate_tangents().unwrap();
+    let cube = meshes.add(cube);
+    let height_map = assets.load("cube/paramap_bump.jpg");
+    let normal_map = assets.load("cube/paramap_normal.jpg");
+    let parallax = ParallaxExtension {
+        algorithm: ParallaxAlgo::ReliefMapping,
+        max_height_layers: 64.0,
+        ..default()
+    };
+    let phong = Parallax {
+        base: PhongMaterial {
+            color: Color::WHITE,
+            shininess: 32.0,
+            base_texture: assets.load("cube/paramap_color.jpg"),
+            normal_map: normal_map.clone(),
+        },
+        height_map: height_map.clone(),
+        cone_map: None,
+        parallax,
+    };
+    cmd.spawn(MaterialMeshBundle {
+        mesh: cube.clone(),
+        material: phong_materials.add(phong),
+        transform: Transform::from_xyz(-0.75, 0.0, 0.75),
+        ..default()
+    })
+    .insert(Spin);
+    let standard = Parallax {
+        base: StandardMaterial {
+            base_color_texture: Some(assets.load("cube/paramap_color.jpg")),
+            normal_map_texture: Some(normal_map),
+            perceptual_roughness: 0.4,
+            ..default()
+        },
+        height_map,
+        cone_map: None,
+        parallax,
+    };
+    cmd.spawn(MaterialMeshBundle {
+        mesh: cube,
+        material: standard_materials.add(standard),
+        transform: Transform::from_xyz(0.75, 0.0, -0.75),
+        ..default()
+    })
+    .insert(Spin);
+}
@@ -0,0 +1,169 @@
+//! CPU-side texture baking for the [`ParallaxAlgo`]s that need more than
+//! a height map.
+//!
+//! This also generates height maps and normal maps from one another,
+//! see [`height_map_from_normal_map`] and [`normal_map_from_height_map`].
+//!
+//! Baking is slow, you should bake textures once, save them and load them
+//! as regular assets rather than baking them each time the game starts.
+//!
+//! [`ParallaxAlgo`]: crate::ParallaxAlgo
+
+// Image sizes are far from the limits of those casts.
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+
+use anyhow::{bail, Result};
+use bevy::{
+    prelude::Image,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{HeightChannel, HeightConvention};
+
+mod cone;
+mod mip;
+mod normal;
+mod sobel;
+
+pub use cone::{cone_map, relaxed_cone_map};
+pub use mip::max_mip_chain;
+pub use normal::{height_map_from_normal_map, NormalIntegration};
+pub use sobel::{normal_map_from_height_map, EdgeMode, GradientKernel, NormalGeneration};
+
+/// The heights of a height map, as the shader sees them.
+///
+/// Values are between 0 and 1, 0 being the tallest and 1 the deepest.
+struct Heights {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+impl Heights {
+    /// Read the `channel` of `image`, like `sample_height` in the shader
+    /// with the given [`HeightConvention`] and [`HeightChannel`].
+    ///
+    /// Channels missing from the format read as 0, except alpha which reads as 1,
+    /// like when the GPU samples them.
+    fn from_image(
+        image: &Image,
+        convention: HeightConvention,
+        channel: HeightChannel,
+    ) -> Result<Self> {
+        let size = image.texture_descriptor.size;
+        if image.texture_descriptor.dimension != TextureDimension::D2
+            || size.depth_or_array_layers != 1
+        {
+            bail!("Height maps must be 2D images, got {size:?}");
+        }
+        // The size of a texel, and the offset of each of its channels in RGBA order.
+        // sRGB formats are read as raw bytes, like the shader does once
+        // `linearize_textures` set their linear format.
+        let (texel_size, offsets): (usize, &[usize]) = match image.texture_descriptor.format {
+            TextureFormat::R8Unorm => (1, &[0]),
+            TextureFormat::Rg8Unorm => (2, &[0, 1]),
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => (4, &[0, 1, 2, 3]),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => (4, &[2, 1, 0, 3]),
+            TextureFormat::R16Unorm => (2, &[0]),
+            TextureFormat::Rgba16Unorm => (8, &[0, 2, 4, 6]),
+            TextureFormat::R32Float => (4, &[0]),
+            TextureFormat::Rgba32Float => (16, &[0, 4, 8, 12]),
+            format => bail!("Unsupported height map texture format: {format:?}"),
+        };
+        let decode = match texel_size / offsets.len() {
+            1 => |bytes: &[u8]| f32::from(bytes[0]) / f32::from(u8::MAX),
+            2 => |bytes: &[u8]| {
+                f32::from(u16::from_le_bytes([bytes[0], bytes[1]])) / f32::from(u16::MAX)
+            },
+            _ => |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        };
+        let channel = match channel {
+            HeightChannel::R => 0,
+            HeightChannel::G => 1,
+            HeightChannel::B => 2,
+            HeightChannel::A => 3,
+        };
+        let missing = if channel == 3 { 1.0 } else { 0.0 };
+        let read = |texel: &[u8]| {
+            let value = offsets
+                .get(channel)
+                .map_or(missing, |offset| decode(&texel[*offset..]));
+            match convention {
+                HeightConvention::Depth => value,
+                HeightConvention::Height => 1.0 - value,
+            }
+        };
+        let mut values: Vec<f32> = image.data.chunks_exact(texel_size).map(read).collect();
+        let (width, height) = (size.width as usize, size.height as usize);
+        if values.len() < width * height {
+            bail!(
+                "Height map data doesn't match its size: {} pixels for a {width}×{height} image",
+                values.len()
+            );
+        }
+        // Drop the other mip levels, if any.
+        values.truncate(width * height);
+        Ok(Self {
+            width,
+            height,
+            values,
+        })
+    }
+
+    /// The height at pixel `(x, y)`, wrapping around the image borders.
+    fn get(&self, x: isize, y: isize) -> f32 {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.values[x + y * self.width]
+    }
+}
+
+/// Create a height map of `width`×`height` from `depths` between 0 and 1,
+/// stored with the given [`HeightConvention`] in the given [`HeightChannel`].
+///
+/// Heights in the red channel are stored in a single-channel image, otherwise
+/// all channels of an RGBA image hold the height.
+fn height_image(
+    width: usize,
+    height: usize,
+    depths: impl Iterator<Item = f32>,
+    convention: HeightConvention,
+    channel: HeightChannel,
+) -> Image {
+    let values = depths.map(move |depth| match convention {
+        HeightConvention::Depth => depth,
+        HeightConvention::Height => 1.0 - depth,
+    });
+    if channel == HeightChannel::R {
+        return r8_image(width, height, values);
+    }
+    let data = values
+        .flat_map(|v| [(v.clamp(0.0, 1.0) * 255.0).round() as u8; 4])
+        .collect();
+    let size = Extent3d {
+        width: width as u32,
+        height: height as u32,
+        depth_or_array_layers: 1,
+    };
+    Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8Unorm)
+}
+
+/// Create a single-channel linear image of `width`×`height`, `values` between 0 and 1.
+fn r8_image(width: usize, height: usize, values: impl Iterator<Item = f32>) -> Image {
+    let data = values.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+    let size = Extent3d {
+        width: width as u32,
+        height: height as u32,
+        depth_or_array_layers: 1,
+    };
+    Image::new(
+        size,
+        TextureDimension::D2,
+        data.collect(),
+        TextureFormat::R8Unorm,
+    )
+}
@@ -0,0 +1,217 @@
+//! Cone maps for [`ParallaxAlgo::ConeStepMapping`] and
+//! [`ParallaxAlgo::RelaxedConeStepMapping`].
+//!
+//! A cone map stores, for each texel of a height map, the ratio between the
+//! radius and the height of a cone with its apex on the height map surface.
+//! The shader steps along the view ray to the border of the cone, rather than
+//! by fixed steps.
+//!
+//! Like in the original [cone step mapping] implementation, the texture stores
+//! the square root of the ratio, to give more precision to narrow cones.
+//!
+//! [cone step mapping]: https://web.archive.org/web/20160311154423/http://www.lonesock.net/files/ConeStepMapping.pdf
+//! [`ParallaxAlgo::ConeStepMapping`]: crate::ParallaxAlgo::ConeStepMapping
+//! [`ParallaxAlgo::RelaxedConeStepMapping`]: crate::ParallaxAlgo::RelaxedConeStepMapping
+
+use anyhow::Result;
+use bevy::{
+    prelude::Image,
+    tasks::{ComputeTaskPool, ParallelSlice, TaskPool},
+};
+
+use super::{r8_image, Heights};
+use crate::{HeightChannel, HeightConvention};
+
+/// Bake a cone map for [`ParallaxAlgo::ConeStepMapping`] from a `height_map`.
+///
+/// The cones are the widest that contain no part of the height map. The
+/// height map is assumed to tile.
+///
+/// `convention` and `channel` are the [`ParallaxMaterial::height_convention`]
+/// and [`ParallaxMaterial::height_channel`] of the material the height map is
+/// read with. The cone map itself is always read from its red channel.
+///
+/// # Errors
+///
+/// If the `height_map` is not a 2D image or its format is not supported.
+///
+/// [`ParallaxAlgo::ConeStepMapping`]: crate::ParallaxAlgo::ConeStepMapping
+/// [`ParallaxMaterial::height_convention`]: crate::ParallaxMaterial::height_convention
+/// [`ParallaxMaterial::height_channel`]: crate::ParallaxMaterial::height_channel
+pub fn cone_map(
+    height_map: &Image,
+    convention: HeightConvention,
+    channel: HeightChannel,
+) -> Result<Image> {
+    let heights = Heights::from_image(height_map, convention, channel)?;
+    Ok(cone_ratios(&heights, |src, src_depth, offset| {
+        let dst_depth = heights.get(src.0 + offset.0, src.1 + offset.1);
+        let (x, y) = (offset.0 as f32, offset.1 as f32);
+        (dst_depth < src_depth).then(|| heights.uv_length(x, y) / (src_depth - dst_depth))
+    }))
+}
+
+/// Bake a cone map for [`ParallaxAlgo::RelaxedConeStepMapping`] from a `height_map`.
+///
+/// Relaxed cones are wider than the ones from [`cone_map`]: they may contain
+/// parts of the height map, but a view ray crosses the height map surface at most
+/// once within the cone. See [GPU Gems 3, chapter 18]. The height map is assumed
+/// to tile.
+///
+/// `convention` and `channel` are read like with [`cone_map`].
+///
+/// # Errors
+///
+/// If the `height_map` is not a 2D image or its format is not supported.
+///
+/// [GPU Gems 3, chapter 18]: https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-18-relaxed-cone-stepping-relief-mapping
+/// [`ParallaxAlgo::RelaxedConeStepMapping`]: crate::ParallaxAlgo::RelaxedConeStepMapping
+pub fn relaxed_cone_map(
+    height_map: &Image,
+    convention: HeightConvention,
+    channel: HeightChannel,
+) -> Result<Image> {
+    let heights = Heights::from_image(height_map, convention, channel)?;
+    Ok(cone_ratios(&heights, |src, src_depth, offset| {
+        let dst_depth = heights.get(src.0 + offset.0, src.1 + offset.1);
+        // The ray from the top of `src` through the surface at `dst` enters the
+        // height map at `dst`, the cone must not contain where the ray exits it.
+        let ((x, y), exit_depth) = heights.ray_exit(src, offset, dst_depth)?;
+        (exit_depth < src_depth).then(|| heights.uv_length(x, y) / (src_depth - exit_depth))
+    }))
+}
+
+/// The cone map of `heights`, with `ratio` the widest possible cone ratio at
+/// the `src` texel, of depth `src_depth`, given the texel at `offset` from it.
+fn cone_ratios<F>(heights: &Heights, ratio: F) -> Image
+where
+    F: Fn((isize, isize), f32, (isize, isize)) -> Option<f32> + Send + Sync,
+{
+    let (width, height) = (heights.width as isize, heights.height as isize);
+    let max_radius = width.max(height) / 2 + 1;
+    let texel_uv = 1.0 / width.max(height) as f32;
+    let shallowest = heights.values.iter().copied().fold(1.0, f32::min);
+
+    let pool = ComputeTaskPool::init(TaskPool::default);
+    let rows: Vec<isize> = (0..height).collect();
+    let rows = rows.par_chunk_map(pool, 1, |rows| {
+        let row = |y| (0..width).map(move |x| (x, y));
+        let cone = |src @ (x, y)| {
+            let src_depth = heights.get(x, y);
+            let mut best = 1.0_f32;
+            for radius in 1..=max_radius {
+                // No texel in the ring is closer than `radius * texel_uv`, and the
+                // cone ratio can't be lower than `distance / (src_depth - shallowest)`.
+                if radius as f32 * texel_uv >= best * (src_depth - shallowest) {
+                    break;
+                }
+                for offset in ring(radius) {
+                    if let Some(ratio) = ratio(src, src_depth, offset) {
+                        best = best.min(ratio);
+                    }
+                }
+            }
+            best.sqrt()
+        };
+        rows.iter()
+            .flat_map(|y| row(*y))
+            .map(cone)
+            .collect::<Vec<_>>()
+    });
+    r8_image(heights.width, heights.height, rows.into_iter().flatten())
+}
+
+/// The offsets of texels at exactly `radius` texels from `(0, 0)`,
+/// in chebyshev distance.
+fn ring(radius: isize) -> impl Iterator<Item = (isize, isize)> {
+    let side = -radius..radius;
+    let top = side.clone().map(move |x| (x, -radius));
+    let right = side.clone().map(move |y| (radius, y));
+    let bottom = side.clone().map(move |x| (-x, radius));
+    let left = side.map(move |y| (-radius, -y));
+    top.chain(right).chain(bottom).chain(left)
+}
+
+impl Heights {
+    /// The length in UV units of a `(x, y)` vector in texels.
+    fn uv_length(&self, x: f32, y: f32) -> f32 {
+        let (x, y) = (x / self.width as f32, y / self.height as f32);
+        x.hypot(y)
+    }
+
+    /// Where the ray from the top of `src` through the surface of the texel at
+    /// `offset` from it, of depth `dst_depth`, goes back above the surface.
+    ///
+    /// Returns the offset from `src` in texels and depth of the exit point,
+    /// `None` if the ray reaches the bottom of the height map first.
+    fn ray_exit(
+        &self,
+        src: (isize, isize),
+        offset: (isize, isize),
+        dst_depth: f32,
+    ) -> Option<((f32, f32), f32)> {
+        if dst_depth <= 0.0 {
+            return None;
+        }
+        let (x, y) = (offset.0 as f32, offset.1 as f32);
+        // Step one texel at a time.
+        let step = dst_depth / x.abs().max(y.abs());
+        for i in 1_u32.. {
+            let depth = (i as f32).mul_add(step, dst_depth);
+            if depth >= 1.0 {
+                break;
+            }
+            let (x, y) = (x * depth / dst_depth, y * depth / dst_depth);
+            let surface = self.get(src.0 + x.round() as isize, src.1 + y.round() as isize);
+            if depth < surface {
+                return Some(((x, y), depth));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::height_image, *};
+    use HeightChannel::{G, R};
+    use HeightConvention::{Depth, Height};
+
+    #[test]
+    fn flat_map_has_widest_cones() {
+        let flat = r8_image(8, 8, std::iter::repeat_n(0.5, 64));
+        for cones in [cone_map(&flat, Depth, R), relaxed_cone_map(&flat, Depth, R)] {
+            assert!(cones.unwrap().data.iter().all(|ratio| *ratio == u8::MAX));
+        }
+    }
+
+    #[test]
+    fn single_spike_bounds_cones() {
+        // All texels are at the bottom, except the top one at (1, 4).
+        let depths = (0..64).map(|i| if i == 1 + 4 * 8 { 0.0 } else { 1.0 });
+        let cones = cone_map(&r8_image(8, 8, depths), Depth, R).unwrap();
+        let ratio_at = |x: usize, y: usize| cones.data[x + y * 8];
+        // The cone at the top of the spike contains nothing.
+        assert_eq!(ratio_at(1, 4), u8::MAX);
+        // Elsewhere, the cone reaches the top of the spike, 1 deep.
+        let expected = |x: f32, y: f32| ((x.hypot(y) / 8.0).sqrt() * 255.0).round() as u8;
+        assert_eq!(ratio_at(3, 4), expected(2.0, 0.0));
+        assert_eq!(ratio_at(1, 3), expected(0.0, 1.0));
+        assert_eq!(ratio_at(3, 6), expected(2.0, 2.0));
+        // The height map tiles, (7, 4) is 2 texels from the spike through the edge.
+        assert_eq!(ratio_at(7, 4), expected(2.0, 0.0));
+    }
+
+    #[test]
+    fn reads_height_convention_and_channel() {
+        // Arbitrary but deterministic heights.
+        let depths = || (0_u32..64).map(|i| ((i * 37 + 11) % 64) as f32 / 63.0);
+        let depth_map = r8_image(8, 8, depths());
+        let height_map = height_image(8, 8, depths(), Height, G);
+        for bake in [cone_map, relaxed_cone_map] {
+            let expected = bake(&depth_map, Depth, R).unwrap();
+            let cones = bake(&height_map, Height, G).unwrap();
+            assert_eq!(cones.data, expected.data);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Max mip chains for [`ParallaxAlgo::QuadtreeDisplacementMapping`].
+//!
+//! [`ParallaxAlgo::QuadtreeDisplacementMapping`]: crate::ParallaxAlgo::QuadtreeDisplacementMapping
+
+use anyhow::Result;
+use bevy::prelude::Image;
+
+use super::{height_image, Heights};
+use crate::{HeightChannel, HeightConvention};
+
+/// Build the mip chain of a `height_map` for [`ParallaxAlgo::QuadtreeDisplacementMapping`].
+///
+/// Each texel of a mip level holds the tallest height of the texels it
+/// covers in the previous level, rather than their average. The returned
+/// image has the same size as `height_map` and a full mip chain, use it as
+/// the [`ParallaxMaterial::height_map`].
+///
+/// `convention` and `channel` are the [`ParallaxMaterial::height_convention`]
+/// and [`ParallaxMaterial::height_channel`] of the material: the height map is
+/// read with them, and the returned image stores heights the same way, with
+/// 8 bits of precision.
+///
+/// # Errors
+///
+/// If the `height_map` is not a 2D image or its format is not supported.
+///
+/// [`ParallaxAlgo::QuadtreeDisplacementMapping`]: crate::ParallaxAlgo::QuadtreeDisplacementMapping
+/// [`ParallaxMaterial::height_map`]: crate::ParallaxMaterial::height_map
+/// [`ParallaxMaterial::height_convention`]: crate::ParallaxMaterial::height_convention
+/// [`ParallaxMaterial::height_channel`]: crate::ParallaxMaterial::height_channel
+pub fn max_mip_chain(
+    height_map: &Image,
+    convention: HeightConvention,
+    channel: HeightChannel,
+) -> Result<Image> {
+    let heights = Heights::from_image(height_map, convention, channel)?;
+    let (mut width, mut height) = (heights.width, heights.height);
+    let mut level = heights.values;
+    let level_image = |width, height, level: &[f32]| {
+        height_image(width, height, level.iter().copied(), convention, channel)
+    };
+    let mut image = level_image(width, height, &level);
+
+    let mut level_count = 1;
+    while width > 1 || height > 1 {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        // The texels of `level` covered by `(x, y)` in the next level. With odd
+        // sizes, next level texels cover up to 3×3 texels.
+        let tallest = |(x, y): (usize, usize)| {
+            let xs = x * width / next_width..((x + 1) * width).div_ceil(next_width);
+            let ys = y * height / next_height..((y + 1) * height).div_ceil(next_height);
+            let covered = ys.flat_map(|y| xs.clone().map(move |x| x + y * width));
+            // 0 is the tallest.
+            covered.map(|i| level[i]).fold(1.0, f32::min)
+        };
+        let next_texels = (0..next_height).flat_map(|y| (0..next_width).map(move |x| (x, y)));
+        level = next_texels.map(tallest).collect();
+        let next_image = level_image(next_width, next_height, &level);
+        image.data.extend_from_slice(&next_image.data);
+        (width, height) = (next_width, next_height);
+        level_count += 1;
+    }
+    image.texture_descriptor.mip_level_count = level_count;
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::r8_image, *};
+    use HeightChannel::{G, R};
+    use HeightConvention::{Depth, Height};
+
+    #[test]
+    fn levels_hold_tallest_children() {
+        // Arbitrary but deterministic heights.
+        let depths = (0_u32..64).map(|i| ((i * 37 + 11) % 64) as f32 / 63.0);
+        let chain = max_mip_chain(&r8_image(8, 8, depths), Depth, R).unwrap();
+        assert_eq!(chain.texture_descriptor.mip_level_count, 4);
+
+        let (mut start, mut size) = (0, 8);
+        while size > 1 {
+            let level = &chain.data[start..start + size * size];
+            let next = &chain.data[start + size * size..];
+            let half = size / 2;
+            for (x, y) in (0..half).flat_map(|y| (0..half).map(move |x| (x, y))) {
+                let child = |dx, dy| level[2 * x + dx + (2 * y + dy) * size];
+                let tallest = child(0, 0)
+                    .min(child(1, 0))
+                    .min(child(0, 1))
+                    .min(child(1, 1));
+                assert_eq!(
+                    next[x + y * half],
+                    tallest,
+                    "level of size {half} at {x},{y}"
+                );
+            }
+            start += size * size;
+            size = half;
+        }
+        assert_eq!(chain.data.len(), start + 1);
+    }
+
+    #[test]
+    fn writes_height_convention_and_channel() {
+        let depths = || (0_u32..64).map(|i| ((i * 37 + 11) % 64) as f32 / 63.0);
+        let depth_chain = max_mip_chain(&r8_image(8, 8, depths()), Depth, R).unwrap();
+        let height_map = height_image(8, 8, depths(), Height, G);
+        let chain = max_mip_chain(&height_map, Height, G).unwrap();
+        assert_eq!(chain.texture_descriptor.mip_level_count, 4);
+        // Each RGBA texel holds the inverted depth of the depth chain.
+        let expected = depth_chain
+            .data
+            .iter()
+            .flat_map(|depth| [u8::MAX - depth; 4]);
+        assert!(chain.data.iter().copied().eq(expected));
+    }
+}
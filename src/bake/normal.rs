@@ -0,0 +1,337 @@
+//! Height maps from normal maps, see [`height_map_from_normal_map`].
+//!
+//! A tangent space normal map stores the slope of the surface at each texel.
+//! The height map is the surface with the closest slopes, found by solving
+//! the Poisson equation `∇²h = ∇·g`, `g` being the slopes from the normal map.
+//! See <https://stannum.io/blog/0IwyJ-> for an explanation of the method.
+//!
+//! The equation is solved with Jacobi iterations, first on a downscaled normal
+//! map, then on larger and larger versions, using the previous solution as a
+//! starting point. This converges much faster than iterating on the full size
+//! normal map.
+
+use anyhow::{bail, Result};
+use bevy::{
+    math::Vec3,
+    prelude::Image,
+    render::render_resource::{TextureDimension, TextureFormat},
+    tasks::{ComputeTaskPool, ParallelSlice, TaskPool},
+};
+
+use super::height_image;
+use crate::{HeightChannel, HeightConvention};
+
+/// Below this size, the normal map is not downscaled further.
+const MIN_LEVEL_SIZE: usize = 16;
+
+/// Avoid infinite slopes on normals perpendicular to the surface.
+const MIN_NORMAL_Z: f32 = 0.1;
+
+/// How to integrate a normal map, see [`height_map_from_normal_map`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalIntegration {
+    /// How many Jacobi iterations to run at each resolution.
+    ///
+    /// Higher values get closer to the exact solution, but take longer.
+    /// Default is 64.
+    pub iterations: u32,
+
+    /// Whether the normal map tiles.
+    ///
+    /// With `tiling`, the edges of the normal map are stitched to the opposite
+    /// edges, otherwise, they are free. Default is `true`.
+    pub tiling: bool,
+
+    /// Whether the normal map is authored for DirectX,
+    /// see [`ParallaxMaterial::flip_normal_map_y`].
+    ///
+    /// Default is `false`.
+    ///
+    /// [`ParallaxMaterial::flip_normal_map_y`]: crate::ParallaxMaterial::flip_normal_map_y
+    pub flip_y: bool,
+
+    /// How to store the heights, see [`ParallaxMaterial::height_convention`].
+    ///
+    /// Default is [`HeightConvention::Depth`].
+    ///
+    /// [`ParallaxMaterial::height_convention`]: crate::ParallaxMaterial::height_convention
+    pub convention: HeightConvention,
+
+    /// The channel storing the heights, see [`ParallaxMaterial::height_channel`].
+    /// With [`HeightChannel::R`], the height map has a single channel,
+    /// otherwise all channels hold the height.
+    ///
+    /// Default is [`HeightChannel::R`].
+    ///
+    /// [`ParallaxMaterial::height_channel`]: crate::ParallaxMaterial::height_channel
+    pub channel: HeightChannel,
+}
+impl Default for NormalIntegration {
+    fn default() -> Self {
+        Self {
+            iterations: 64,
+            tiling: true,
+            flip_y: false,
+            convention: HeightConvention::Depth,
+            channel: HeightChannel::R,
+        }
+    }
+}
+
+/// Compute a height map from a tangent space `normal_map`.
+///
+/// The resulting height map uses the whole value range, by default from the
+/// tallest point in black to the deepest in white, see
+/// [`NormalIntegration::convention`]. Adjust [`ParallaxMaterial::height_depth`]
+/// to the actual depth of the surface.
+///
+/// Normal maps only store slopes, so the height map is an approximation, it
+/// works best on smooth surfaces. Note that the bytes of the normal map are read
+/// as-is, even if the image has an sRGB format.
+///
+/// # Errors
+///
+/// If the `normal_map` is not a 2D image or its format is not supported.
+///
+/// [`ParallaxMaterial::height_depth`]: crate::ParallaxMaterial::height_depth
+pub fn height_map_from_normal_map(
+    normal_map: &Image,
+    integration: NormalIntegration,
+) -> Result<Image> {
+    let slopes = Slopes::from_normal_map(normal_map, integration)?;
+    let heights = slopes.integrate(integration.iterations);
+
+    let (min, max) = heights.iter().fold((f32::MAX, f32::MIN), |(min, max), h| {
+        (min.min(*h), max.max(*h))
+    });
+    let range = (max - min).max(f32::EPSILON);
+    let depths = heights.into_iter().map(|h| (max - h) / range);
+    Ok(height_image(
+        slopes.width,
+        slopes.height,
+        depths,
+        integration.convention,
+        integration.channel,
+    ))
+}
+
+/// The slopes of a surface, in height units per texel.
+struct Slopes {
+    width: usize,
+    height: usize,
+    tiling: bool,
+    /// The slope toward increasing `x`.
+    x: Vec<f32>,
+    /// The slope toward increasing `y`, down in the image.
+    y: Vec<f32>,
+}
+impl Slopes {
+    fn from_normal_map(image: &Image, integration: NormalIntegration) -> Result<Self> {
+        let size = image.texture_descriptor.size;
+        if image.texture_descriptor.dimension != TextureDimension::D2
+            || size.depth_or_array_layers != 1
+        {
+            bail!("Normal maps must be 2D images, got {size:?}");
+        }
+        let unorm8 = |byte: u8| f32::from(byte) / f32::from(u8::MAX);
+        let unorm16 = |bytes: &[u8]| {
+            f32::from(u16::from_le_bytes([bytes[0], bytes[1]])) / f32::from(u16::MAX)
+        };
+        let float32 = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let data = &image.data;
+        // Normal map values, between 0 and 1, `z` is 0 on two-component normal maps.
+        let values: Vec<Vec3> = match image.texture_descriptor.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => data
+                .chunks_exact(4)
+                .map(|p| Vec3::new(unorm8(p[0]), unorm8(p[1]), unorm8(p[2])))
+                .collect(),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => data
+                .chunks_exact(4)
+                .map(|p| Vec3::new(unorm8(p[2]), unorm8(p[1]), unorm8(p[0])))
+                .collect(),
+            TextureFormat::Rg8Unorm => data
+                .chunks_exact(2)
+                .map(|p| Vec3::new(unorm8(p[0]), unorm8(p[1]), 0.0))
+                .collect(),
+            TextureFormat::Rgba16Unorm => data
+                .chunks_exact(8)
+                .map(|p| Vec3::new(unorm16(&p[0..2]), unorm16(&p[2..4]), unorm16(&p[4..6])))
+                .collect(),
+            TextureFormat::Rgba32Float => data
+                .chunks_exact(16)
+                .map(|p| Vec3::new(float32(&p[0..4]), float32(&p[4..8]), float32(&p[8..12])))
+                .collect(),
+            format => bail!("Unsupported normal map texture format: {format:?}"),
+        };
+        let two_component = image.texture_descriptor.format == TextureFormat::Rg8Unorm;
+        let (width, height) = (size.width as usize, size.height as usize);
+        if values.len() < width * height {
+            bail!(
+                "Normal map data doesn't match its size: {} pixels for a {width}×{height} image",
+                values.len()
+            );
+        }
+        // The normal map's +y is up in the image, opposite to our `y`.
+        let y_sign = if integration.flip_y { -1.0 } else { 1.0 };
+        let (x, y) = values[..width * height]
+            .iter()
+            .map(|value| {
+                let mut normal = *value * 2.0 - 1.0;
+                if two_component {
+                    normal.z = (1.0 - normal.truncate().length_squared()).max(0.0).sqrt();
+                }
+                let z = normal.z.max(MIN_NORMAL_Z);
+                (-normal.x / z, y_sign * normal.y / z)
+            })
+            .unzip();
+        Ok(Self {
+            width,
+            height,
+            tiling: integration.tiling,
+            x,
+            y,
+        })
+    }
+
+    /// The neighbors of texel `(x, y)`, as their index and the expected
+    /// height difference from `(x, y)` to them.
+    fn neighbors(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (width, height) = (self.width, self.height);
+        let index = |x: usize, y: usize| x + y * width;
+        let at = index(x, y);
+        // Wrap around when tiling, skip texels outside of the image otherwise.
+        let offset = move |value: usize, delta: isize, size: usize| {
+            let moved = value as isize + delta;
+            if (0..size as isize).contains(&moved) {
+                Some(moved as usize)
+            } else {
+                self.tiling
+                    .then(|| moved.rem_euclid(size as isize) as usize)
+            }
+        };
+        let left = offset(x, -1, width).map(|nx| {
+            let other = index(nx, y);
+            (other, -f32::midpoint(self.x[at], self.x[other]))
+        });
+        let right = offset(x, 1, width).map(|nx| {
+            let other = index(nx, y);
+            (other, f32::midpoint(self.x[at], self.x[other]))
+        });
+        let up = offset(y, -1, height).map(|ny| {
+            let other = index(x, ny);
+            (other, -f32::midpoint(self.y[at], self.y[other]))
+        });
+        let down = offset(y, 1, height).map(|ny| {
+            let other = index(x, ny);
+            (other, f32::midpoint(self.y[at], self.y[other]))
+        });
+        [left, right, up, down].into_iter().flatten()
+    }
+
+    /// Half-size slopes, each texel covering 2×2 texels of `self`.
+    fn downscale(&self) -> Self {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut x = Vec::with_capacity(width * height);
+        let mut y = Vec::with_capacity(width * height);
+        for cy in 0..height {
+            for cx in 0..width {
+                let footprint = (2 * cy..(2 * cy + 2).min(self.height))
+                    .flat_map(|fy| (2 * cx..(2 * cx + 2).min(self.width)).map(move |fx| (fx, fy)));
+                let (mut sum_x, mut sum_y, mut count) = (0.0, 0.0, 0.0);
+                for (fx, fy) in footprint {
+                    sum_x += self.x[fx + fy * self.width];
+                    sum_y += self.y[fx + fy * self.width];
+                    count += 1.0;
+                }
+                // A texel is twice as large, so are the height differences between texels.
+                x.push(2.0 * sum_x / count);
+                y.push(2.0 * sum_y / count);
+            }
+        }
+        Self {
+            width,
+            height,
+            tiling: self.tiling,
+            x,
+            y,
+        }
+    }
+
+    /// The heights with the closest slopes to `self`.
+    fn integrate(&self, iterations: u32) -> Vec<f32> {
+        let mut heights = if self.width.min(self.height) > MIN_LEVEL_SIZE {
+            let coarse = self.downscale();
+            let coarse_heights = coarse.integrate(iterations);
+            let (width, height) = (self.width, self.height);
+            (0..width * height)
+                .map(|i| coarse_heights[(i % width) / 2 + (i / width) / 2 * coarse.width])
+                .collect()
+        } else {
+            vec![0.0; self.width * self.height]
+        };
+
+        let pool = ComputeTaskPool::init(TaskPool::default);
+        let rows: Vec<usize> = (0..self.height).collect();
+        for _ in 0..iterations {
+            let previous = &heights;
+            let new_rows = rows.par_chunk_map(pool, 8, |rows| {
+                let row = |y| (0..self.width).map(move |x| (x, y));
+                let jacobi = |(x, y)| {
+                    let (mut sum, mut count) = (0.0, 0_u32);
+                    for (neighbor, difference) in self.neighbors(x, y) {
+                        sum += previous[neighbor] - difference;
+                        count += 1;
+                    }
+                    if count == 0 {
+                        0.0
+                    } else {
+                        sum / count as f32
+                    }
+                };
+                rows.iter()
+                    .flat_map(|y| row(*y))
+                    .map(jacobi)
+                    .collect::<Vec<_>>()
+            });
+            heights = new_rows.into_iter().flatten().collect();
+        }
+        heights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::*;
+    use crate::bake::{normal_map_from_height_map, r8_image, NormalGeneration};
+
+    #[test]
+    fn smooth_bump_round_trips() {
+        const SIZE: usize = 64;
+        // A tiling bump using the whole depth range.
+        let wave = |i: usize| (TAU * i as f32 / SIZE as f32).cos();
+        let depths =
+            (0..SIZE * SIZE).map(|i| 0.25f32.mul_add(wave(i % SIZE) + wave(i / SIZE), 0.5));
+        let bump = r8_image(SIZE, SIZE, depths);
+
+        let generation = NormalGeneration::default();
+        let normals = normal_map_from_height_map(&bump, generation).unwrap();
+        let heights = height_map_from_normal_map(&normals, NormalIntegration::default()).unwrap();
+        let round_trip = normal_map_from_height_map(&heights, generation).unwrap();
+
+        let decode = |texel: &[u8]| {
+            let normal = Vec3::new(texel[0].into(), texel[1].into(), texel[2].into());
+            normal / 127.5 - 1.0
+        };
+        let original = normals.data.chunks_exact(4).map(decode);
+        let computed = round_trip.data.chunks_exact(4).map(decode);
+        for (i, (original, computed)) in original.zip(computed).enumerate() {
+            let error = original.distance(computed);
+            assert!(
+                error < 0.05,
+                "{error} off at texel {i}: {original} {computed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Normal maps from height maps, see [`normal_map_from_height_map`].
+//!
+//! The slope of the height map at each texel is computed with a 3×3 gradient
+//! kernel, then converted into a tangent space normal.
+
+use anyhow::Result;
+use bevy::{
+    math::Vec3,
+    prelude::Image,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use super::Heights;
+use crate::{HeightChannel, HeightConvention};
+
+/// The kernel used to compute the slope of the height map, see [`NormalGeneration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GradientKernel {
+    /// The [Sobel operator](https://en.wikipedia.org/wiki/Sobel_operator).
+    #[default]
+    Sobel,
+    /// The Scharr operator, more accurate than Sobel on diagonal slopes.
+    Scharr,
+}
+impl GradientKernel {
+    /// The weights of the center and corner texels on the kernel's axis.
+    ///
+    /// They sum to one half, so that the kernel computes the slope in height
+    /// per texel, the texels on each side being 2 texels apart.
+    const fn weights(self) -> (f32, f32) {
+        match self {
+            Self::Sobel => (2.0 / 8.0, 1.0 / 8.0),
+            Self::Scharr => (10.0 / 32.0, 3.0 / 32.0),
+        }
+    }
+}
+
+/// How to read texels past the edges of the height map, see [`NormalGeneration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeMode {
+    /// The height map tiles, texels past an edge are read from the opposite edge.
+    #[default]
+    Repeat,
+    /// Texels past an edge are read from the edge.
+    Clamp,
+    /// Texels past an edge are read mirrored back into the height map.
+    Mirror,
+}
+impl EdgeMode {
+    /// The coordinate to read from for `value`, on an axis of `size` texels.
+    fn apply(self, value: isize, size: isize) -> isize {
+        match self {
+            Self::Repeat => value.rem_euclid(size),
+            Self::Clamp => value.clamp(0, size - 1),
+            Self::Mirror => {
+                let period = value.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+        }
+    }
+}
+
+/// How to compute a normal map, see [`normal_map_from_height_map`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalGeneration {
+    /// How steep the slopes are, how many texels deep is the full height map.
+    ///
+    /// To match the parallax effect, use `height_depth * width`, `width`
+    /// being the width of the height map in texels.
+    /// Default is 32.0.
+    pub strength: f32,
+
+    /// The kernel used to compute the slopes, default is [`GradientKernel::Sobel`].
+    pub kernel: GradientKernel,
+
+    /// How to read texels past the edges, default is [`EdgeMode::Repeat`].
+    pub edges: EdgeMode,
+
+    /// Whether to author the normal map for DirectX, the normal map should then
+    /// be used with [`ParallaxMaterial::flip_normal_map_y`] set.
+    ///
+    /// Default is `false`.
+    ///
+    /// [`ParallaxMaterial::flip_normal_map_y`]: crate::ParallaxMaterial::flip_normal_map_y
+    pub flip_y: bool,
+
+    /// How to read the height map values, see [`ParallaxMaterial::height_convention`].
+    ///
+    /// Default is [`HeightConvention::Depth`].
+    ///
+    /// [`ParallaxMaterial::height_convention`]: crate::ParallaxMaterial::height_convention
+    pub convention: HeightConvention,
+
+    /// The channel of the height map to read, see [`ParallaxMaterial::height_channel`].
+    ///
+    /// Default is [`HeightChannel::R`].
+    ///
+    /// [`ParallaxMaterial::height_channel`]: crate::ParallaxMaterial::height_channel
+    pub channel: HeightChannel,
+}
+impl Default for NormalGeneration {
+    fn default() -> Self {
+        Self {
+            strength: 32.0,
+            kernel: GradientKernel::Sobel,
+            edges: EdgeMode::Repeat,
+            flip_y: false,
+            convention: HeightConvention::Depth,
+            channel: HeightChannel::R,
+        }
+    }
+}
+
+/// Compute a tangent space normal map from a `height_map`.
+///
+/// The `height_map` is read like the shader reads it with the
+/// [`NormalGeneration::convention`] and [`NormalGeneration::channel`]
+/// settings, by default the red channel, black being the tallest. The normal
+/// map has the `Rgba8Unorm` format, so it is linear like normal maps should be.
+///
+/// # Errors
+///
+/// If the `height_map` is not a 2D image or its format is not supported.
+pub fn normal_map_from_height_map(
+    height_map: &Image,
+    generation: NormalGeneration,
+) -> Result<Image> {
+    let heights = Heights::from_image(height_map, generation.convention, generation.channel)?;
+    let (width, height) = (heights.width as isize, heights.height as isize);
+    let (center, corner) = generation.kernel.weights();
+    let weights = Vec3::new(corner, center, corner);
+    let edges = generation.edges;
+    let depth = |x: isize, y: isize| heights.get(edges.apply(x, width), edges.apply(y, height));
+    // The normal map's +y is up in the image, with OpenGL conventions.
+    let y_sign = if generation.flip_y { 1.0 } else { -1.0 };
+
+    let mut data = Vec::with_capacity(heights.values.len() * 4);
+    for y in 0..height {
+        for x in 0..width {
+            // How much deeper the height map gets toward +x and +y.
+            let slope_x = weights.dot(Vec3::new(
+                depth(x + 1, y - 1) - depth(x - 1, y - 1),
+                depth(x + 1, y) - depth(x - 1, y),
+                depth(x + 1, y + 1) - depth(x - 1, y + 1),
+            ));
+            let slope_y = weights.dot(Vec3::new(
+                depth(x - 1, y + 1) - depth(x - 1, y - 1),
+                depth(x, y + 1) - depth(x, y - 1),
+                depth(x + 1, y + 1) - depth(x + 1, y - 1),
+            ));
+            let strength = generation.strength;
+            let normal = Vec3::new(strength * slope_x, y_sign * strength * slope_y, 1.0);
+            let encoded = normal.normalize() * 0.5 + 0.5;
+            for channel in encoded.to_array() {
+                data.push((channel * 255.0).round() as u8);
+            }
+            data.push(u8::MAX);
+        }
+    }
+    let size = Extent3d {
+        width: heights.width as u32,
+        height: heights.height as u32,
+        depth_or_array_layers: 1,
+    };
+    Ok(Image::new(
+        size,
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bake::r8_image;
+
+    #[test]
+    fn flat_map_has_zero_slope() {
+        let flat = r8_image(4, 4, std::iter::repeat_n(0.3, 16));
+        let normals = normal_map_from_height_map(&flat, NormalGeneration::default()).unwrap();
+        for texel in normals.data.chunks_exact(4) {
+            assert_eq!(texel, [128, 128, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn edge_modes_at_borders() {
+        // A ramp getting deeper toward +x, 0 on the left edge, 1 on the right.
+        let ramp = (0..16).map(|i| (i % 4) as f32 / 3.0);
+        let ramp = r8_image(4, 4, ramp);
+        let normal_x = |edges, x: usize| {
+            let generation = NormalGeneration {
+                edges,
+                ..NormalGeneration::default()
+            };
+            let normals = normal_map_from_height_map(&ramp, generation).unwrap();
+            normals.data[(x + 4) * 4]
+        };
+        // Inside the height map, the edges are not read.
+        let inner = normal_x(EdgeMode::Clamp, 1);
+        assert!(inner > 128);
+        assert_eq!(normal_x(EdgeMode::Repeat, 1), inner);
+        assert_eq!(normal_x(EdgeMode::Mirror, 1), inner);
+        // On the left edge, repeat reads the deepest texels of the right edge,
+        // so the slope is reversed, clamp and mirror read the edge itself.
+        assert!(normal_x(EdgeMode::Repeat, 0) < 128);
+        assert!(normal_x(EdgeMode::Clamp, 0) > 128);
+        assert_eq!(normal_x(EdgeMode::Clamp, 0), normal_x(EdgeMode::Mirror, 0));
+    }
+}
@@ -0,0 +1,743 @@
+//! Parallax mapping on top of any [`Material`], see [`Parallax`].
+
+use std::{
+    any::TypeId,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use bevy::{
+    core_pipeline::{
+        core_3d::{AlphaMask3d, Opaque3d, Transparent3d},
+        prepass::{AlphaMask3dPrepass, Opaque3dPrepass},
+    },
+    ecs::system::{
+        lifetimeless::{Read, SRes},
+        SystemParamItem,
+    },
+    pbr::{
+        prepare_materials, DrawMesh, DrawPrepass, MaterialPipeline, MaterialPipelineKey,
+        RenderMaterials, SetMaterialBindGroup, SetMeshBindGroup, SetMeshViewBindGroup,
+        SetPrepassViewBindGroup, Shadow,
+    },
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_component::{ComponentUniforms, DynamicUniformIndex},
+        mesh::MeshVertexBufferLayout,
+        render_asset::RenderAssets,
+        render_phase::{
+            PhaseItem, RenderCommand, RenderCommandResult, SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            encase::UniformBuffer, AsBindGroup, AsBindGroupError, BindGroup, BindGroupDescriptor,
+            BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+            BindingType, BufferBindingType, BufferInitDescriptor, BufferUsages,
+            OwnedBindingResource, PreparedBindGroup, RenderPipelineDescriptor, SamplerBindingType,
+            ShaderDefVal, ShaderRef, ShaderStages, ShaderType, SpecializedMeshPipelineError,
+            TextureSampleType, TextureViewDimension,
+        },
+        renderer::RenderDevice,
+        texture::FallbackImage,
+        ExtractSchedule, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    overrides::{
+        extract_parallax_overrides, replace_draw_function, DrawMaterial, ParallaxOverrideUniform,
+    },
+    quality::{limit_prepared_materials, QualityKey, QualityLimited},
+    HeightChannel, HeightConvention, ParallaxAlgo,
+};
+
+/// Any [`Material`] `M`, with parallax mapping.
+///
+/// The `base` material keeps its own bind group, shaders and pipeline
+/// specialization, the height map and cone map are bound next to it, in the
+/// bind group `3`. Wrap `StandardMaterial`, a toon or an unlit material to get
+/// parallax mapping without forking it:
+///
+/// ```ignore
+/// let material = Parallax {
+///     base: StandardMaterial::from(assets.load("brick_color.png")),
+///     height_map: assets.load("brick_height.png"),
+///     cone_map: None,
+///     parallax: ParallaxExtension::default(),
+/// };
+/// ```
+///
+/// `StandardMaterial` is drawn with a copy of bevy's PBR shader that displaces
+/// its uv. Other materials must import `bevy_mod_paramap::parallax_extension`
+/// in their fragment shader, after the view bindings, and pass their uv
+/// through `parallax_extension_uv` before sampling their textures:
+///
+/// ```wgsl
+/// #import bevy_pbr::mesh_view_bindings
+/// #import bevy_mod_paramap::parallax_extension
+///
+/// @fragment
+/// fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+///     let uv = parallax_extension_uv(in.world_position, in.world_normal, in.world_tangent, in.uv);
+///     return color * textureSample(base_texture, base_sampler, uv);
+/// }
+/// ```
+///
+/// When the material isn't wrapped in [`Parallax`], `parallax_extension_uv`
+/// returns the uv unchanged, so the shader works with and without it.
+///
+/// Unlike [`ParallaxMaterial`], only the uv of the material is displaced,
+/// the other [`ParallaxMaterial`] features such as self shadowing or depth
+/// writing are not available. The [`ParallaxQuality`] limits and
+/// [`ParallaxOverride`]s do apply.
+///
+/// Add a [`ParallaxPlugin`] for `M`, and the [`ParallaxMaterialPlugin`] to
+/// your app to use it. See the `phong` example.
+///
+/// **WARNING**: this _assumes_ the mesh has tangents set. Unlike with [`ParallaxMaterial`],
+/// they are not generated automatically.
+///
+/// [`ParallaxMaterial`]: crate::ParallaxMaterial
+/// [`ParallaxMaterialPlugin`]: crate::ParallaxMaterialPlugin
+/// [`ParallaxOverride`]: crate::ParallaxOverride
+/// [`ParallaxQuality`]: crate::ParallaxQuality
+#[derive(Debug, Clone, TypeUuid)]
+#[uuid = "4e2d8fb5-6a8b-4d46-9c1e-0d7b2c35a9f1"]
+pub struct Parallax<M: Material> {
+    /// The material to add parallax mapping to.
+    pub base: M,
+
+    /// The height map, see [`ParallaxMaterial::height_map`].
+    ///
+    /// [`ParallaxMaterial::height_map`]: crate::ParallaxMaterial::height_map
+    pub height_map: Handle<Image>,
+
+    /// The cone map, only read with [`ParallaxAlgo::ConeStepMapping`],
+    /// see [`ParallaxMaterial::cone_map`].
+    ///
+    /// [`ParallaxMaterial::cone_map`]: crate::ParallaxMaterial::cone_map
+    pub cone_map: Option<Handle<Image>>,
+
+    /// The parallax mapping settings.
+    pub parallax: ParallaxExtension,
+}
+
+/// The parallax mapping settings of a [`Parallax`] material.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, FromReflect)]
+#[reflect(Default, Debug)]
+pub struct ParallaxExtension {
+    /// How deep the offset introduced by the height map should be,
+    /// see [`ParallaxMaterial::height_depth`].
+    ///
+    /// [`ParallaxMaterial::height_depth`]: crate::ParallaxMaterial::height_depth
+    pub height_depth: f32,
+
+    /// The parallax mapping algorithm, see [`ParallaxMaterial::algorithm`].
+    ///
+    /// [`ParallaxMaterial::algorithm`]: crate::ParallaxMaterial::algorithm
+    pub algorithm: ParallaxAlgo,
+
+    /// How many layers to split the height map in,
+    /// see [`ParallaxMaterial::max_height_layers`].
+    ///
+    /// [`ParallaxMaterial::max_height_layers`]: crate::ParallaxMaterial::max_height_layers
+    pub max_height_layers: f32,
+
+    /// How many refinement iterations to run,
+    /// see [`ParallaxMaterial::refinement_steps`].
+    ///
+    /// [`ParallaxMaterial::refinement_steps`]: crate::ParallaxMaterial::refinement_steps
+    pub refinement_steps: u32,
+
+    /// Whether white is the top or bottom of the height map,
+    /// see [`ParallaxMaterial::height_convention`].
+    ///
+    /// [`ParallaxMaterial::height_convention`]: crate::ParallaxMaterial::height_convention
+    pub height_convention: HeightConvention,
+
+    /// The channel of the height map holding the heights,
+    /// see [`ParallaxMaterial::height_channel`].
+    ///
+    /// [`ParallaxMaterial::height_channel`]: crate::ParallaxMaterial::height_channel
+    pub height_channel: HeightChannel,
+
+    /// How deep the mesh surface is in the relief,
+    /// see [`ParallaxMaterial::height_mid_level`].
+    ///
+    /// [`ParallaxMaterial::height_mid_level`]: crate::ParallaxMaterial::height_mid_level
+    pub height_mid_level: f32,
+}
+impl Default for ParallaxExtension {
+    /// Same defaults as [`ParallaxMaterial`].
+    ///
+    /// [`ParallaxMaterial`]: crate::ParallaxMaterial
+    fn default() -> Self {
+        Self {
+            height_depth: 0.1,
+            algorithm: default(),
+            max_height_layers: 16.0,
+            refinement_steps: 5,
+            height_convention: HeightConvention::Depth,
+            height_channel: HeightChannel::R,
+            height_mid_level: 0.0,
+        }
+    }
+}
+
+use uniform::ParallaxExtensionUniform;
+
+// encase's `ShaderType` derive generates `check` functions next to the struct,
+// that recent rustc flags as unused.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::render::render_resource::ShaderType;
+
+    use super::ParallaxExtension;
+
+    /// The GPU representation of a [`ParallaxExtension`].
+    #[derive(Clone, Default, ShaderType)]
+    pub struct ParallaxExtensionUniform {
+        height_depth: f32,
+        max_height_layers: f32,
+        refinement_steps: u32,
+        height_mid_level: f32,
+    }
+    impl From<&'_ ParallaxExtension> for ParallaxExtensionUniform {
+        fn from(parallax: &ParallaxExtension) -> Self {
+            Self {
+                height_depth: parallax.height_depth,
+                max_height_layers: parallax.max_height_layers,
+                refinement_steps: parallax.refinement_steps,
+                height_mid_level: parallax.height_mid_level,
+            }
+        }
+    }
+}
+
+/// How many bindings [`Parallax::as_bind_group`] appends to the bindings of
+/// the base material: the uniform, and the height and cone maps with their
+/// samplers.
+const PARALLAX_BINDINGS: usize = 5;
+
+impl<M: Material> AsBindGroup for Parallax<M> {
+    type Data = ParallaxKey<M>;
+
+    /// The bind group of the base material, with the parallax bindings
+    /// appended to its `bindings`. They are bound in the bind group `3`.
+    fn as_bind_group(
+        &self,
+        layout: &BindGroupLayout,
+        render_device: &RenderDevice,
+        images: &RenderAssets<Image>,
+        fallback_image: &FallbackImage,
+    ) -> Result<PreparedBindGroup<Self::Data>, AsBindGroupError> {
+        let base = self
+            .base
+            .as_bind_group(layout, render_device, images, fallback_image)?;
+        let height_map = images
+            .get(&self.height_map)
+            .ok_or(AsBindGroupError::RetryNextUpdate)?;
+        let cone_map = match &self.cone_map {
+            Some(cone_map) => images
+                .get(cone_map)
+                .ok_or(AsBindGroupError::RetryNextUpdate)?,
+            None => fallback_image,
+        };
+        let mut buffer = UniformBuffer::new(Vec::new());
+        buffer
+            .write(&ParallaxExtensionUniform::from(&self.parallax))
+            .unwrap();
+        let uniform = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("parallax_extension_uniform"),
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            contents: buffer.as_ref(),
+        });
+        let mut bindings = base.bindings;
+        bindings.extend([
+            OwnedBindingResource::Buffer(uniform),
+            OwnedBindingResource::TextureView(height_map.texture_view.clone()),
+            OwnedBindingResource::Sampler(height_map.sampler.clone()),
+            OwnedBindingResource::TextureView(cone_map.texture_view.clone()),
+            OwnedBindingResource::Sampler(cone_map.sampler.clone()),
+        ]);
+        Ok(PreparedBindGroup {
+            bindings,
+            bind_group: base.bind_group,
+            data: ParallaxKey::new(base.data, self),
+        })
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        M::bind_group_layout(render_device)
+    }
+}
+
+/// The pipeline key of a [`Parallax`] material: the key of its base
+/// material, and the parallax settings.
+pub struct ParallaxKey<M: Material> {
+    base: M::Data,
+    algorithm: ParallaxAlgo,
+    has_cone_map: bool,
+    height_convention: HeightConvention,
+    height_channel: HeightChannel,
+    quality: QualityKey,
+    layout: Option<ParallaxLayout>,
+    base_pipeline: Option<BasePipeline<M>>,
+}
+impl<M: Material> ParallaxKey<M> {
+    fn new(base: M::Data, material: &Parallax<M>) -> Self {
+        Self {
+            base,
+            algorithm: material.parallax.algorithm,
+            has_cone_map: material.cone_map.is_some(),
+            height_convention: material.parallax.height_convention,
+            height_channel: material.parallax.height_channel,
+            // Set in the render world, see `limit_prepared_materials`
+            // and `prepare_parallax_keys`.
+            quality: QualityKey::default(),
+            layout: None,
+            base_pipeline: None,
+        }
+    }
+
+    /// The shader defs enabling parallax mapping in `parallax_extension_uv`,
+    /// within the [`ParallaxQuality`] limits.
+    ///
+    /// [`ParallaxQuality`]: crate::ParallaxQuality
+    fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        if !self.quality.parallax_mapping {
+            return Vec::new();
+        }
+        let mut defs: Vec<ShaderDefVal> = vec!["PARALLAX_EXTENSION".into()];
+        let algorithm = self.quality.algorithm(self.algorithm, self.has_cone_map);
+        defs.extend(algorithm.shader_defs().iter().map(|def| (*def).into()));
+        defs.extend(self.quality.shader_defs());
+        if self.height_convention == HeightConvention::Height {
+            defs.push("INVERTED_HEIGHT_MAP".into());
+        }
+        if let Some(def) = self.height_channel.shader_def() {
+            defs.push(def.into());
+        }
+        defs
+    }
+}
+impl<M: Material> Clone for ParallaxKey<M>
+where
+    M::Data: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            layout: self.layout.clone(),
+            base_pipeline: self.base_pipeline.clone(),
+            ..*self
+        }
+    }
+}
+impl<M: Material> PartialEq for ParallaxKey<M>
+where
+    M::Data: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.base == other.base
+            && self.algorithm == other.algorithm
+            && self.has_cone_map == other.has_cone_map
+            && self.height_convention == other.height_convention
+            && self.height_channel == other.height_channel
+            && self.quality == other.quality
+            && self.layout == other.layout
+            && self.base_pipeline.is_some() == other.base_pipeline.is_some()
+    }
+}
+impl<M: Material> Eq for ParallaxKey<M> where M::Data: Eq {}
+impl<M: Material> Hash for ParallaxKey<M>
+where
+    M::Data: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.base.hash(state);
+        self.algorithm.hash(state);
+        self.has_cone_map.hash(state);
+        self.height_convention.hash(state);
+        self.height_channel.hash(state);
+        self.quality.hash(state);
+        self.layout.hash(state);
+        self.base_pipeline.is_some().hash(state);
+    }
+}
+impl<M: Material> QualityLimited for ParallaxKey<M> {
+    fn quality_mut(&mut self) -> &mut QualityKey {
+        &mut self.quality
+    }
+}
+
+/// The [`MaterialPipeline`] of the base material, passed to its
+/// [`Material::specialize`].
+///
+/// There is a single one per material type, so all of them compare equal.
+#[derive(Resource)]
+struct BasePipeline<M: Material>(Arc<MaterialPipeline<M>>);
+impl<M: Material> Clone for BasePipeline<M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+impl<M: Material> FromWorld for BasePipeline<M> {
+    fn from_world(world: &mut World) -> Self {
+        Self(Arc::new(MaterialPipeline::from_world(world)))
+    }
+}
+
+/// The layout of the [`Parallax`] bind groups, bound at index `3`.
+///
+/// Binding `0` is the [`ParallaxOverride`] of the entity, the
+/// [`PARALLAX_BINDINGS`] follow.
+///
+/// [`ParallaxOverride`]: crate::ParallaxOverride
+#[derive(Resource, Clone, Debug)]
+struct ParallaxLayout(BindGroupLayout);
+impl FromWorld for ParallaxLayout {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        Self(
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("parallax_extension_layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(ParallaxOverrideUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(ParallaxExtensionUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                    texture(2),
+                    sampler(3),
+                    texture(4),
+                    sampler(5),
+                ],
+            }),
+        )
+    }
+}
+impl PartialEq for ParallaxLayout {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id() == other.0.id()
+    }
+}
+impl Eq for ParallaxLayout {}
+impl Hash for ParallaxLayout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.id().hash(state);
+    }
+}
+
+/// Set the [`ParallaxLayout`] and [`BasePipeline`] on the keys of the
+/// prepared `Parallax<M>` materials.
+#[allow(clippy::needless_pass_by_value)]
+fn prepare_parallax_keys<M: Material>(
+    layout: Res<ParallaxLayout>,
+    base_pipeline: Res<BasePipeline<M>>,
+    mut materials: ResMut<RenderMaterials<Parallax<M>>>,
+) where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    for material in materials.0.values_mut() {
+        let key = &mut material.key;
+        if key.layout.is_none() {
+            key.layout = Some(layout.clone());
+        }
+        if key.base_pipeline.is_none() {
+            key.base_pipeline = Some(base_pipeline.clone());
+        }
+    }
+}
+
+/// The bind groups of the prepared `Parallax<M>` materials for this frame.
+#[derive(Resource)]
+struct ParallaxBindGroups<M: Material>(HashMap<Handle<Parallax<M>>, BindGroup>);
+impl<M: Material> Default for ParallaxBindGroups<M> {
+    fn default() -> Self {
+        Self(HashMap::default())
+    }
+}
+
+/// Create the [`ParallaxBindGroups`] of this frame.
+///
+/// They bind the [`ParallaxOverrideUniform`]s buffer, which may change every
+/// frame, so they are recreated every frame.
+#[allow(clippy::needless_pass_by_value)]
+fn queue_parallax_bind_groups<M: Material>(
+    layout: Res<ParallaxLayout>,
+    render_device: Res<RenderDevice>,
+    uniforms: Res<ComponentUniforms<ParallaxOverrideUniform>>,
+    materials: Res<RenderMaterials<Parallax<M>>>,
+    mut bind_groups: ResMut<ParallaxBindGroups<M>>,
+) where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    bind_groups.0.clear();
+    let Some(overrides) = uniforms.uniforms().binding() else {
+        return;
+    };
+    for (handle, material) in &materials.0 {
+        let start = material.bindings.len() - PARALLAX_BINDINGS;
+        let mut entries = vec![BindGroupEntry {
+            binding: 0,
+            resource: overrides.clone(),
+        }];
+        let parallax_bindings = material.bindings[start..].iter().zip(1..);
+        entries.extend(parallax_bindings.map(|(resource, binding)| BindGroupEntry {
+            binding,
+            resource: resource.get_binding(),
+        }));
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &entries,
+            label: Some("parallax_extension_bind_group"),
+            layout: &layout.0,
+        });
+        bind_groups.0.insert(handle.clone_weak(), bind_group);
+    }
+}
+
+/// Sets the [`ParallaxBindGroups`] of the item's material at the `I` index,
+/// with the offset of the entity's [`ParallaxOverrideUniform`].
+struct SetParallaxBindGroup<M, const I: usize>(PhantomData<fn() -> M>);
+impl<P: PhaseItem, M: Material, const I: usize> RenderCommand<P> for SetParallaxBindGroup<M, I> {
+    type Param = SRes<ParallaxBindGroups<M>>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = (
+        Read<Handle<Parallax<M>>>,
+        Option<Read<DynamicUniformIndex<ParallaxOverrideUniform>>>,
+    );
+
+    #[inline]
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        (handle, index): (
+            &'w Handle<Parallax<M>>,
+            Option<&'w DynamicUniformIndex<ParallaxOverrideUniform>>,
+        ),
+        bind_groups: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let bind_group = bind_groups.into_inner().0.get(handle);
+        let (Some(bind_group), Some(index)) = (bind_group, index) else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_bind_group(I, bind_group, &[index.index()]);
+        RenderCommandResult::Success
+    }
+}
+
+/// [`DrawMaterial`], also binding the [`ParallaxBindGroups`].
+type DrawParallax<M> = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMaterialBindGroup<Parallax<M>, 1>,
+    SetMeshBindGroup<2>,
+    SetParallaxBindGroup<M, 3>,
+    DrawMesh,
+);
+
+/// [`DrawPrepass`], also binding the [`ParallaxBindGroups`].
+type DrawParallaxPrepass<M> = (
+    SetItemPipeline,
+    SetPrepassViewBindGroup<0>,
+    SetMaterialBindGroup<Parallax<M>, 1>,
+    SetMeshBindGroup<2>,
+    SetParallaxBindGroup<M, 3>,
+    DrawMesh,
+);
+
+impl<M: Material> Material for Parallax<M>
+where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let parallax_key = key.bind_group_data;
+        let base_pipeline = parallax_key
+            .base_pipeline
+            .as_ref()
+            .expect("Parallax materials are specialized once prepared");
+        let base_key = MaterialPipelineKey {
+            mesh_key: key.mesh_key,
+            bind_group_data: parallax_key.base.clone(),
+        };
+        M::specialize(&base_pipeline.0, descriptor, layout, base_key)?;
+        if let Some(fragment) = &mut descriptor.fragment {
+            fragment.shader_defs.extend(parallax_key.shader_defs());
+        }
+        // NOTE: The parallax bind group is bound in all passes, see `ParallaxPlugin`.
+        if let Some(layout) = parallax_key.layout {
+            descriptor.layout.push(layout.0);
+        }
+        Ok(())
+    }
+
+    fn vertex_shader() -> ShaderRef {
+        M::vertex_shader()
+    }
+
+    /// The fragment shader of `M`, or a copy of bevy's PBR shader calling
+    /// `parallax_extension_uv` for `StandardMaterial`.
+    fn fragment_shader() -> ShaderRef {
+        if TypeId::of::<M>() == TypeId::of::<StandardMaterial>() {
+            crate::parallax_standard_shader()
+        } else {
+            M::fragment_shader()
+        }
+    }
+
+    fn prepass_vertex_shader() -> ShaderRef {
+        M::prepass_vertex_shader()
+    }
+
+    fn prepass_fragment_shader() -> ShaderRef {
+        M::prepass_fragment_shader()
+    }
+
+    #[inline]
+    fn alpha_mode(&self) -> AlphaMode {
+        self.base.alpha_mode()
+    }
+
+    #[inline]
+    fn depth_bias(&self) -> f32 {
+        self.base.depth_bias()
+    }
+}
+
+/// Adds the [`MaterialPlugin`] of [`Parallax<M>`], and binds its height maps,
+/// [`ParallaxQuality`] limits and [`ParallaxOverride`]s.
+///
+/// [`ParallaxOverride`]: crate::ParallaxOverride
+/// [`ParallaxQuality`]: crate::ParallaxQuality
+pub struct ParallaxPlugin<M>(PhantomData<fn() -> M>);
+impl<M> Default for ParallaxPlugin<M> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+impl<M: Material> Plugin for ParallaxPlugin<M>
+where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    fn build(&self, app: &mut App) {
+        app.add_plugin(MaterialPlugin::<Parallax<M>>::default());
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<ParallaxLayout>()
+                .init_resource::<BasePipeline<M>>()
+                .init_resource::<ParallaxBindGroups<M>>()
+                .add_system(extract_parallax_overrides::<Parallax<M>>.in_schedule(ExtractSchedule))
+                .add_system(
+                    limit_prepared_materials::<Parallax<M>>
+                        .in_set(RenderSet::Prepare)
+                        .after(prepare_materials::<Parallax<M>>),
+                )
+                .add_system(
+                    prepare_parallax_keys::<M>
+                        .in_set(RenderSet::Prepare)
+                        .after(prepare_materials::<Parallax<M>>),
+                )
+                .add_system(queue_parallax_bind_groups::<M>.in_set(RenderSet::Queue));
+            let world = &mut render_app.world;
+            replace_draw_function::<Opaque3d, DrawMaterial<Parallax<M>>, DrawParallax<M>>(world);
+            replace_draw_function::<AlphaMask3d, DrawMaterial<Parallax<M>>, DrawParallax<M>>(world);
+            replace_draw_function::<Transparent3d, DrawMaterial<Parallax<M>>, DrawParallax<M>>(
+                world,
+            );
+            replace_draw_function::<Shadow, DrawPrepass<Parallax<M>>, DrawParallaxPrepass<M>>(
+                world,
+            );
+            replace_draw_function::<
+                Opaque3dPrepass,
+                DrawPrepass<Parallax<M>>,
+                DrawParallaxPrepass<M>,
+            >(world);
+            replace_draw_function::<
+                AlphaMask3dPrepass,
+                DrawPrepass<Parallax<M>>,
+                DrawParallaxPrepass<M>,
+            >(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::pbr::StandardMaterialKey;
+
+    use super::*;
+    use crate::{ParallaxMaterial, ParallaxQuality};
+
+    #[test]
+    fn wrapped_materials_are_distinct_assets() {
+        let standard = Parallax::<StandardMaterial>::TYPE_UUID;
+        assert_ne!(standard, Parallax::<ParallaxMaterial>::TYPE_UUID);
+        assert_ne!(standard, StandardMaterial::TYPE_UUID);
+    }
+
+    #[test]
+    fn key_keeps_base_key_and_limits_parallax() {
+        let base = StandardMaterial::default();
+        let material = Parallax {
+            height_map: Handle::default(),
+            cone_map: None,
+            parallax: ParallaxExtension {
+                algorithm: ParallaxAlgo::ConeStepMapping,
+                height_convention: HeightConvention::Height,
+                ..default()
+            },
+            base: base.clone(),
+        };
+        let mut key = ParallaxKey::new(StandardMaterialKey::from(&base), &material);
+        assert!(key.base == StandardMaterialKey::from(&base));
+        let defs = key.shader_defs();
+        let def = |name: &str| ShaderDefVal::from(name);
+        assert!(defs.contains(&def("PARALLAX_EXTENSION")));
+        assert!(defs.contains(&def("INVERTED_HEIGHT_MAP")));
+        // Cone step mapping falls back to parallax occlusion mapping without a cone map.
+        assert!(!defs.contains(&def("CONE_STEP_MAPPING")));
+
+        key.quality = ParallaxQuality::Off.limits().into();
+        assert!(key.shader_defs().is_empty());
+    }
+}
@@ -0,0 +1,300 @@
+//! Fill in missing height maps from normal maps and the reverse,
+//! see [`GenerateHeightMaps`] and [`GenerateNormalMaps`].
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use anyhow::Result;
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+
+use crate::{
+    bake::{
+        height_map_from_normal_map, normal_map_from_height_map, NormalGeneration, NormalIntegration,
+    },
+    HeightChannel, HeightConvention, ParallaxMaterial,
+};
+
+/// Generate a height map for [`ParallaxMaterial`]s without one.
+///
+/// When this resource exists, [`ParallaxMaterial`]s with a default
+/// `height_map` handle get one computed from their `normal_map_texture`
+/// with [`height_map_from_normal_map`], in a background task.
+///
+/// Materials sharing a normal map share the generated height map.
+/// [`NormalIntegration::flip_y`], `convention` and `channel` are ignored, the
+/// material's `flip_normal_map_y`, `height_convention` and `height_channel`
+/// are used instead. The texture is generated again when the content of its
+/// source changes, for example when hot-reloading it.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct GenerateHeightMaps(pub NormalIntegration);
+
+/// Generate a normal map for [`ParallaxMaterial`]s without one.
+///
+/// When this resource exists, [`ParallaxMaterial`]s with a default
+/// `normal_map_texture` handle get one computed from their `height_map`
+/// with [`normal_map_from_height_map`], in a background task.
+///
+/// Materials sharing a height map share the generated normal map.
+/// [`NormalGeneration::flip_y`], `convention` and `channel` are ignored, the
+/// material's `flip_normal_map_y`, `height_convention` and `height_channel`
+/// are used instead. The texture is generated again when the content of its
+/// source changes, for example when hot-reloading it.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct GenerateNormalMaps(pub NormalGeneration);
+
+/// A texture of [`ParallaxMaterial`] that can be generated from another.
+#[derive(Debug, Clone, Copy)]
+enum MaterialTexture {
+    HeightMap,
+    NormalMap,
+}
+impl MaterialTexture {
+    const fn get(self, material: &ParallaxMaterial) -> &Handle<Image> {
+        match self {
+            Self::HeightMap => &material.height_map,
+            Self::NormalMap => &material.normal_map_texture,
+        }
+    }
+    const fn get_mut(self, material: &mut ParallaxMaterial) -> &mut Handle<Image> {
+        match self {
+            Self::HeightMap => &mut material.height_map,
+            Self::NormalMap => &mut material.normal_map_texture,
+        }
+    }
+}
+
+/// How a material reads its textures, the bakers must read and write them
+/// the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TextureLayout {
+    flip_y: bool,
+    convention: HeightConvention,
+    channel: HeightChannel,
+}
+impl TextureLayout {
+    const fn of(material: &ParallaxMaterial) -> Self {
+        Self {
+            flip_y: material.flip_normal_map_y,
+            convention: material.height_convention,
+            channel: material.height_channel,
+        }
+    }
+}
+
+/// A texture to generate another from, and how to read it.
+type Source = (Handle<Image>, TextureLayout);
+
+/// A hash of the content of `image`, its format excluded.
+///
+/// The bakers read sRGB and linear images the same way, so changing the format
+/// of a source, as [`linearize_textures`] does, doesn't change what is generated.
+///
+/// [`linearize_textures`]: crate::linearize_textures
+fn content_hash(image: &Image) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.size().to_array().map(f32::to_bits).hash(&mut hasher);
+    image.data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The state of texture generation, see [`generate_height_maps`]
+/// and [`generate_normal_maps`].
+#[derive(Default)]
+pub struct GeneratedTextures {
+    /// Materials waiting for a texture, by source texture.
+    waiting: HashMap<Source, Vec<Handle<ParallaxMaterial>>>,
+    /// Textures being computed, by source texture.
+    running: HashMap<Source, Task<Result<Image>>>,
+    /// Already computed textures, by source texture.
+    done: HashMap<Source, Handle<Image>>,
+    /// The [`content_hash`] of the source textures of `running` and `done`.
+    source_hashes: HashMap<HandleId, u64>,
+}
+impl GeneratedTextures {
+    /// Set the `target` texture of materials where it's missing, using `bake`
+    /// on their `source` texture.
+    ///
+    /// `bake` is called with the source texture and how the material reads
+    /// its textures. Textures are generated again when their source changes.
+    fn update<F>(
+        &mut self,
+        (source, target): (MaterialTexture, MaterialTexture),
+        events: &mut EventReader<AssetEvent<ParallaxMaterial>>,
+        image_events: &mut EventReader<AssetEvent<Image>>,
+        materials: &mut Assets<ParallaxMaterial>,
+        images: &mut Assets<Image>,
+        bake: F,
+    ) where
+        F: Fn(&Image, TextureLayout) -> Result<Image> + Clone + Send + 'static,
+    {
+        for event in events {
+            let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+                continue;
+            };
+            let Some(material) = materials.get(handle) else {
+                continue;
+            };
+            let missing = |texture: MaterialTexture| *texture.get(material) == Handle::default();
+            if !missing(target) || missing(source) {
+                continue;
+            }
+            let source = (
+                source.get(material).clone_weak(),
+                TextureLayout::of(material),
+            );
+            self.waiting
+                .entry(source)
+                .or_default()
+                .push(handle.clone_weak());
+        }
+
+        for event in image_events {
+            let (AssetEvent::Modified { handle } | AssetEvent::Removed { handle }) = event else {
+                continue;
+            };
+            let known_hash = self.source_hashes.get(&handle.id()).copied();
+            if known_hash.is_some() && images.get(handle).map(content_hash) == known_hash {
+                // Only the format changed.
+                continue;
+            }
+            self.source_hashes.remove(&handle.id());
+            let outdated: Vec<Source> = (self.done.keys())
+                .chain(self.running.keys())
+                .filter(|(texture, _)| texture == handle)
+                .cloned()
+                .collect();
+            for outdated in outdated {
+                self.running.remove(&outdated);
+                let Some(generated) = self.done.remove(&outdated) else {
+                    continue;
+                };
+                if let AssetEvent::Removed { .. } = event {
+                    continue;
+                }
+                // Replace the texture generated from the previous source.
+                let users = materials
+                    .iter()
+                    .filter(|(_, material)| *target.get(material) == generated)
+                    .map(|(id, _)| Handle::weak(id));
+                self.waiting.entry(outdated).or_default().extend(users);
+            }
+        }
+
+        let pool = AsyncComputeTaskPool::get();
+        for source in self.waiting.keys() {
+            if self.running.contains_key(source) || self.done.contains_key(source) {
+                continue;
+            }
+            let (texture, layout) = source;
+            let Some(image) = images.get(texture) else {
+                continue;
+            };
+            self.source_hashes.insert(texture.id(), content_hash(image));
+            let image = image.clone();
+            let (bake, layout) = (bake.clone(), *layout);
+            let task = pool.spawn(async move { bake(&image, layout) });
+            self.running.insert((texture.clone_weak(), layout), task);
+        }
+
+        let mut finished = Vec::new();
+        self.running.retain(|source, task| {
+            if !task.is_finished() {
+                return true;
+            }
+            let source = (source.0.clone_weak(), source.1);
+            finished.push((source, future::block_on(future::poll_once(task))));
+            false
+        });
+        for (source, result) in finished {
+            match result {
+                Some(Ok(texture)) => {
+                    self.done.insert(source, images.add(texture));
+                }
+                Some(Err(err)) => {
+                    warn!("Couldn't generate a {target:?} from {:?}: {err}", source.0);
+                    self.waiting.remove(&source);
+                }
+                None => {}
+            }
+        }
+
+        for (source, texture) in &self.done {
+            let Some(waiting) = self.waiting.remove(source) else {
+                continue;
+            };
+            for handle in waiting {
+                if let Some(material) = materials.get_mut(&handle) {
+                    *target.get_mut(material) = texture.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Compute height maps for [`ParallaxMaterial`]s without one,
+/// see [`GenerateHeightMaps`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn generate_height_maps(
+    settings: Res<GenerateHeightMaps>,
+    mut textures: Local<GeneratedTextures>,
+    mut events: EventReader<AssetEvent<ParallaxMaterial>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut materials: ResMut<Assets<ParallaxMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let integration = settings.0;
+    textures.update(
+        (MaterialTexture::NormalMap, MaterialTexture::HeightMap),
+        &mut events,
+        &mut image_events,
+        &mut materials,
+        &mut images,
+        move |normal_map, layout| {
+            let integration = NormalIntegration {
+                flip_y: layout.flip_y,
+                convention: layout.convention,
+                channel: layout.channel,
+                ..integration
+            };
+            height_map_from_normal_map(normal_map, integration)
+        },
+    );
+}
+
+/// Compute normal maps for [`ParallaxMaterial`]s without one,
+/// see [`GenerateNormalMaps`].
+#[allow(clippy::needless_pass_by_value)]
+pub fn generate_normal_maps(
+    settings: Res<GenerateNormalMaps>,
+    mut textures: Local<GeneratedTextures>,
+    mut events: EventReader<AssetEvent<ParallaxMaterial>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut materials: ResMut<Assets<ParallaxMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let generation = settings.0;
+    textures.update(
+        (MaterialTexture::HeightMap, MaterialTexture::NormalMap),
+        &mut events,
+        &mut image_events,
+        &mut materials,
+        &mut images,
+        move |height_map, layout| {
+            let generation = NormalGeneration {
+                flip_y: layout.flip_y,
+                convention: layout.convention,
+                channel: layout.channel,
+                ..generation
+            };
+            normal_map_from_height_map(height_map, generation)
+        },
+    );
+}
@@ -0,0 +1,257 @@
+//! Use the height maps referenced by glTF materials, see [`register_gltf_height_maps`].
+
+use anyhow::Result;
+use bevy::{
+    asset::AssetPath,
+    gltf::{Gltf, GltfMesh},
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+    utils::HashMap,
+};
+use futures_lite::future;
+use serde::Deserialize;
+
+use crate::{ParallaxMaterial, ParallaxRegistry};
+
+/// The material `extras` key holding the height map of a glTF material.
+pub const GLTF_HEIGHT_MAP_KEY: &str = "PARAMAP_height_map";
+
+/// The material `extras` key holding the `height_depth` of a glTF material.
+pub const GLTF_HEIGHT_DEPTH_KEY: &str = "PARAMAP_height_depth";
+
+/// The glTF material extension holding the height map of a glTF material.
+pub const GLTF_HEIGHT_EXTENSION: &str = "PARAMAP_materials_height";
+
+/// The parallax settings of a glTF material, as stored in its `extras`.
+#[derive(Deserialize, Debug)]
+struct HeightExtras {
+    #[serde(rename = "PARAMAP_height_map")]
+    height_map: Option<String>,
+    #[serde(rename = "PARAMAP_height_depth")]
+    height_depth: Option<f32>,
+}
+
+/// The parallax settings of a glTF material, as stored in its
+/// [`GLTF_HEIGHT_EXTENSION`] extension.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct HeightExtension {
+    height_texture: TextureInfo,
+    height_depth: Option<f32>,
+}
+
+/// A reference to a texture of a glTF file.
+#[derive(Deserialize, Debug, PartialEq)]
+struct TextureInfo {
+    index: usize,
+}
+
+/// [`HeightExtension`]s, with the index of their material.
+type HeightExtensions = Vec<(usize, HeightExtension)>;
+
+/// The [`HeightExtensions`] of the glTF file `bytes`.
+///
+/// Accepts both `.gltf` and `.glb` files.
+fn read_height_extensions(bytes: &[u8]) -> Result<HeightExtensions> {
+    let gltf = gltf::Gltf::from_slice_without_validation(bytes)?;
+    let mut extensions = Vec::new();
+    for (index, material) in gltf.materials().enumerate() {
+        let Some(extension) = material.extension_value(GLTF_HEIGHT_EXTENSION) else {
+            continue;
+        };
+        match HeightExtension::deserialize(extension) {
+            Ok(extension) => extensions.push((index, extension)),
+            Err(err) => warn!("Invalid {GLTF_HEIGHT_EXTENSION} in material {index}: {err}"),
+        }
+    }
+    Ok(extensions)
+}
+
+/// The glTF files being read for their [`HeightExtension`]s,
+/// see [`register_gltf_height_maps`].
+#[derive(Default)]
+pub struct ReadingGltfs(HashMap<Handle<Gltf>, Task<Result<HeightExtensions>>>);
+
+/// The handle of the texture referenced by `reference` in the `gltf` file.
+///
+/// `#Texture3` is a texture of the glTF file, other references are paths
+/// relative to the glTF file.
+fn height_map(asset_server: &AssetServer, gltf: &AssetPath, reference: &str) -> Handle<Image> {
+    reference.strip_prefix('#').map_or_else(
+        || {
+            let path = gltf
+                .path()
+                .parent()
+                .map_or_else(|| reference.into(), |parent| parent.join(reference));
+            asset_server.load(path)
+        },
+        |label| asset_server.load(AssetPath::new_ref(gltf.path(), Some(label))),
+    )
+}
+
+/// Replace the [`StandardMaterial`]s of glTF files having a height map in
+/// their `extras` or extensions by [`ParallaxMaterial`]s.
+///
+/// Bevy's glTF loader ignores displacement textures. This reads the material
+/// `extras` of loaded glTF files instead, and registers materials with a
+/// [`GLTF_HEIGHT_MAP_KEY`] entry in the [`ParallaxRegistry`]. Blender exports
+/// the custom properties of materials as their `extras`:
+///
+/// ```text
+/// "extras": {
+///     "PARAMAP_height_map": "textures/height_map.png",
+///     "PARAMAP_height_depth": 0.05
+/// }
+/// ```
+///
+/// `PARAMAP_height_map` is either a path relative to the glTF file, or the
+/// label of a texture of the glTF file, such as `"#Texture3"`.
+/// `PARAMAP_height_depth` is optional and sets [`ParallaxMaterial::height_depth`].
+/// The other fields come from the glTF PBR parameters, see
+/// [`ParallaxMaterial::with_standard`].
+///
+/// Materials can also use the [`GLTF_HEIGHT_EXTENSION`] extension, with the
+/// index of a texture of the glTF file:
+///
+/// ```text
+/// "extensions": {
+///     "PARAMAP_materials_height": {
+///         "heightTexture": { "index": 3 },
+///         "heightDepth": 0.05
+///     }
+/// }
+/// ```
+///
+/// Bevy's loader doesn't expose glTF extensions, so the file is read again in
+/// a background task, and its materials are registered a few frames after the
+/// `extras` ones. When a material has both, the `extras` are used.
+///
+/// Materials already in the [`ParallaxRegistry`] are left as is, to override
+/// the file settings. Requires the `gltf` feature.
+#[allow(clippy::needless_pass_by_value)]
+pub fn register_gltf_height_maps(
+    mut reading: Local<ReadingGltfs>,
+    mut events: EventReader<AssetEvent<Gltf>>,
+    gltfs: Res<Assets<Gltf>>,
+    gltf_meshes: Res<Assets<GltfMesh>>,
+    asset_server: Res<AssetServer>,
+    mut registry: ResMut<ParallaxRegistry>,
+) {
+    for event in &mut events {
+        let AssetEvent::Created { handle } = event else {
+            continue;
+        };
+        let (Some(gltf), Some(path)) = (gltfs.get(handle), asset_server.get_handle_path(handle))
+        else {
+            continue;
+        };
+        let server = asset_server.clone();
+        let file = path.path().to_path_buf();
+        let task = IoTaskPool::get().spawn(async move {
+            let bytes = server.asset_io().load_path(&file).await?;
+            read_height_extensions(&bytes)
+        });
+        reading.0.insert(handle.clone_weak(), task);
+        let primitives = gltf
+            .meshes
+            .iter()
+            .filter_map(|mesh| gltf_meshes.get(mesh))
+            .flat_map(|mesh| &mesh.primitives);
+        for primitive in primitives {
+            let (Some(material), Some(extras)) = (&primitive.material, &primitive.material_extras)
+            else {
+                continue;
+            };
+            if registry.contains(material) {
+                continue;
+            }
+            let extras: HeightExtras = match serde_json::from_str(&extras.value) {
+                Ok(extras) => extras,
+                Err(err) => {
+                    warn!("Invalid material extras in {path:?}: {err}");
+                    continue;
+                }
+            };
+            let Some(reference) = extras.height_map else {
+                continue;
+            };
+            let default = ParallaxMaterial::default();
+            let parallax = ParallaxMaterial {
+                height_map: height_map(&asset_server, &path, &reference),
+                height_depth: extras.height_depth.unwrap_or(default.height_depth),
+                ..default
+            };
+            registry.register(material.clone(), parallax);
+        }
+    }
+
+    let mut finished = Vec::new();
+    reading.0.retain(|gltf, task| {
+        if !task.is_finished() {
+            return true;
+        }
+        finished.push((gltf.clone_weak(), future::block_on(future::poll_once(task))));
+        false
+    });
+    for (handle, result) in finished {
+        let (Some(gltf), Some(path)) = (gltfs.get(&handle), asset_server.get_handle_path(&handle))
+        else {
+            continue;
+        };
+        let extensions = match result {
+            Some(Ok(extensions)) => extensions,
+            Some(Err(err)) => {
+                warn!("Couldn't read the material extensions of {path:?}: {err}");
+                continue;
+            }
+            None => continue,
+        };
+        for (index, extension) in extensions {
+            let Some(material) = gltf.materials.get(index) else {
+                continue;
+            };
+            if registry.contains(material) {
+                continue;
+            }
+            let texture = format!("#Texture{}", extension.height_texture.index);
+            let default = ParallaxMaterial::default();
+            let parallax = ParallaxMaterial {
+                height_map: height_map(&asset_server, &path, &texture),
+                height_depth: extension.height_depth.unwrap_or(default.height_depth),
+                ..default
+            };
+            registry.register(material.clone(), parallax);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_height_extensions_of_materials() {
+        let gltf = br#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["PARAMAP_materials_height"],
+            "materials": [
+                {},
+                {
+                    "extensions": {
+                        "PARAMAP_materials_height": {
+                            "heightTexture": { "index": 3 },
+                            "heightDepth": 0.05
+                        }
+                    }
+                },
+                { "extensions": { "PARAMAP_materials_height": { "heightDepth": 0.05 } } }
+            ]
+        }"#;
+        let extensions = read_height_extensions(gltf).unwrap();
+        let expected = HeightExtension {
+            height_texture: TextureInfo { index: 3 },
+            height_depth: Some(0.05),
+        };
+        assert_eq!(extensions, vec![(1, expected)]);
+    }
+}
@@ -4,27 +4,131 @@
 #![doc = include_str!("../README.md")]
 #![deny(missing_docs)]
 #![warn(clippy::pedantic, clippy::nursery)]
+// The README's video links must stay bare for github to embed them, and its
+// list continuations are aligned for github, not rustdoc.
+#![allow(clippy::doc_markdown, clippy::doc_overindented_list_items)]
+
+pub mod bake;
+mod extension;
+mod generate;
+#[cfg(feature = "gltf")]
+mod gltf_extras;
+#[cfg(feature = "inspector")]
+mod inspector_def;
+mod linear;
+mod loader;
+mod overrides;
+mod quality;
+mod swap;
+mod tangents;
+
+pub use extension::{Parallax, ParallaxExtension, ParallaxKey, ParallaxPlugin};
+pub use generate::{
+    generate_height_maps, generate_normal_maps, GenerateHeightMaps, GenerateNormalMaps,
+};
+#[cfg(feature = "gltf")]
+pub use gltf_extras::{
+    register_gltf_height_maps, GLTF_HEIGHT_DEPTH_KEY, GLTF_HEIGHT_EXTENSION, GLTF_HEIGHT_MAP_KEY,
+};
+#[cfg(feature = "inspector")]
+pub use inspector_def::ParallaxInspectorPlugin;
+pub use linear::linearize_textures;
+pub use loader::ParallaxMaterialLoader;
+pub use overrides::ParallaxOverride;
+pub use quality::{ParallaxQuality, ParallaxQualityLimits};
+pub use swap::{swap_standard_materials, ParallaxRegistry};
+pub use tangents::generate_missing_tangents;
 
 use bevy::{
     asset::load_internal_asset,
-    pbr::{MaterialPipeline, MaterialPipelineKey, StandardMaterialUniform},
+    math::Affine2,
+    pbr::{
+        prepare_materials, MaterialPipeline, MaterialPipelineKey, MeshPipelineKey,
+        StandardMaterialUniform, PREPASS_SHADER_HANDLE,
+    },
     prelude::*,
     reflect::TypeUuid,
     render::{
-        mesh::MeshVertexBufferLayout,
+        extract_component::UniformComponentPlugin,
+        extract_resource::ExtractResourcePlugin,
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayout},
         render_asset::RenderAssets,
         render_resource::{
-            AsBindGroup, AsBindGroupShaderType, Face, RenderPipelineDescriptor, ShaderRef,
-            ShaderType, SpecializedMeshPipelineError,
+            AsBindGroup, AsBindGroupShaderType, Face, FragmentState, RenderPipelineDescriptor,
+            ShaderRef, SpecializedMeshPipelineError, VertexFormat,
         },
+        ExtractSchedule, RenderApp, RenderSet,
     },
 };
+use serde::Deserialize;
+
+use overrides::{
+    bind_prepared_overrides, extract_parallax_overrides, queue_override_bind_group,
+    replace_draw_functions, Overridable, OverrideLayout, ParallaxOverrideUniform,
+};
+use quality::{limit_prepared_materials, QualityKey, QualityLimited};
+
+#[cfg(feature = "inspector")]
+use bevy_inspector_egui::prelude::{InspectorOptions, ReflectInspectorOptions};
 
 /// The shader handle for `"parallax_map.wgsl"`.
 #[allow(clippy::unreadable_literal)]
 const PARALLAX_MAPPING_SHADER_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 9592100656503623734);
 
+/// The shader handle for `"parallax_vertex.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_VERTEX_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 11730947025619840563);
+
+/// The shader handle for `"parallax_prepass.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_PREPASS_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 4417583018932257196);
+
+/// The shader handle for `"parallax_bindings.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_BINDINGS_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 13041875963274620191);
+
+/// The shader handle for `"parallax_functions.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_FUNCTIONS_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 7285092360113483528);
+
+/// The shader handle for `"parallax.wgsl"`, the `bevy_mod_paramap::parallax` import.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 16504519322906170742);
+
+/// The shader handle for `"parallax_extension.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_EXTENSION_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 2780462617450195036);
+
+/// The shader handle for `"parallax_standard.wgsl"`.
+#[allow(clippy::unreadable_literal)]
+const PARALLAX_STANDARD_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 14377904208734518153);
+
+/// The fragment shader of [`Parallax<StandardMaterial>`].
+#[cfg(not(feature = "debug"))]
+fn parallax_standard_shader() -> ShaderRef {
+    PARALLAX_STANDARD_SHADER_HANDLE.typed::<Shader>().into()
+}
+/// The fragment shader of [`Parallax<StandardMaterial>`].
+#[cfg(feature = "debug")]
+fn parallax_standard_shader() -> ShaderRef {
+    "parallax_standard.wgsl".into()
+}
+
+/// A second set of UVs for meshes, used by [`HeightMapUv::Uv1`].
+///
+/// Bevy doesn't define it yet, nor does its glTF loader load `TEXCOORD_1`,
+/// so insert it with [`Mesh::insert_attribute`].
+pub const ATTRIBUTE_UV_1: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Uv_1", 1_743_021_559, VertexFormat::Float32x2);
+
 impl From<&'_ ParallaxMaterial> for StandardMaterial {
     fn from(mat: &'_ ParallaxMaterial) -> Self {
         let opt_clone_weak = |opt: &Option<_>| opt.as_ref().map(Handle::clone_weak);
@@ -54,53 +158,179 @@ impl From<&'_ ParallaxMaterial> for StandardMaterial {
 /// [`StandardMaterialKey`] bevy impl.
 ///
 /// [`StandardMaterialKey`]: bevy::pbr::StandardMaterialKey
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct ParallaxMaterialKey {
-    relief_mapping: bool,
+    algorithm: ParallaxAlgo,
+    has_cone_map: bool,
+    self_shadowing: bool,
+    distance_lod: bool,
+    write_depth: bool,
+    parallax_shadows: bool,
+    silhouette: SilhouetteKey,
+    height_convention: HeightConvention,
+    height_channel: HeightChannel,
+    height_map_uv: HeightMapUvKey,
     cull_mode: Option<Face>,
+    quality: QualityKey,
+    override_layout: Option<OverrideLayout>,
+}
+
+/// The [`Silhouette`] variant, without the [`Rect`], so that it can be part
+/// of [`ParallaxMaterialKey`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum SilhouetteKey {
+    None,
+    Clip,
+    Wrap,
+}
+/// The [`HeightMapUv`] variant, without the [`Affine2`], so that it can be part
+/// of [`ParallaxMaterialKey`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum HeightMapUvKey {
+    Shared,
+    Uv0,
+    Uv1,
+}
+impl From<HeightMapUv> for HeightMapUvKey {
+    fn from(height_map_uv: HeightMapUv) -> Self {
+        match height_map_uv {
+            HeightMapUv::Shared => Self::Shared,
+            HeightMapUv::Uv0(_) => Self::Uv0,
+            HeightMapUv::Uv1(_) => Self::Uv1,
+        }
+    }
+}
+impl From<Silhouette> for SilhouetteKey {
+    fn from(silhouette: Silhouette) -> Self {
+        match silhouette {
+            Silhouette::None => Self::None,
+            Silhouette::Clip(_) => Self::Clip,
+            Silhouette::Wrap(_) => Self::Wrap,
+        }
+    }
 }
 impl From<&'_ ParallaxMaterial> for ParallaxMaterialKey {
     fn from(material: &ParallaxMaterial) -> Self {
         Self {
-            relief_mapping: material.algorithm == ParallaxAlgo::ReliefMapping,
+            algorithm: material.algorithm,
+            has_cone_map: material.cone_map.is_some(),
+            self_shadowing: material.self_shadowing,
+            distance_lod: material.distance_lod.is_some(),
+            write_depth: material.write_depth,
+            parallax_shadows: material.parallax_shadows,
+            silhouette: material.silhouette.into(),
+            height_convention: material.height_convention,
+            height_channel: material.height_channel,
+            height_map_uv: material.height_map_uv.into(),
             cull_mode: material.cull_mode,
+            // Set in the render world, see `limit_prepared_materials`
+            // and `bind_prepared_overrides`.
+            quality: QualityKey::default(),
+            override_layout: None,
+        }
+    }
+}
+impl ParallaxMaterialKey {
+    /// This key with its [`ParallaxQuality`] limits applied.
+    fn limited(&self) -> Self {
+        let quality = self.quality;
+        let parallax_mapping = quality.parallax_mapping;
+        Self {
+            algorithm: quality.algorithm(self.algorithm, self.has_cone_map),
+            self_shadowing: self.self_shadowing && quality.self_shadowing,
+            write_depth: self.write_depth && parallax_mapping && quality.write_depth,
+            parallax_shadows: self.parallax_shadows && parallax_mapping && quality.parallax_shadows,
+            ..self.clone()
         }
     }
 }
+impl QualityLimited for ParallaxMaterialKey {
+    fn quality_mut(&mut self) -> &mut QualityKey {
+        &mut self.quality
+    }
+}
+impl Overridable for ParallaxMaterialKey {
+    fn override_layout_mut(&mut self) -> &mut Option<OverrideLayout> {
+        &mut self.override_layout
+    }
+}
 
-/// The GPU representation of the uniform data of a [`ParallaxMaterial`].
-#[derive(Clone, Default, ShaderType)]
-pub struct ParallaxMaterialUniform {
-    /// Doubles as diffuse albedo for non-metallic, specular for metallic and a mix for everything
-    /// in between.
-    pub base_color: Vec4,
-    /// Use a color for user friendliness even though we technically don't use the alpha channel
-    /// Might be used in the future for exposure correction in HDR
-    pub emissive: Vec4,
-    /// Linear perceptual roughness, clamped to [0.089, 1.0] in the shader
-    /// Defaults to minimum of 0.089
-    pub roughness: f32,
-    /// From [0.0, 1.0], dielectric to pure metallic
-    pub metallic: f32,
-    /// Specular intensity for non-metals on a linear scale of [0.0, 1.0]
-    /// defaults to 0.5 which is mapped to 4% reflectance in the shader
-    pub reflectance: f32,
-    /// The shader flags.
-    pub flags: u32,
-    /// When the alpha mode mask flag is set, any base color alpha above this cutoff means fully opaque,
-    /// and any below means fully transparent.
-    pub alpha_cutoff: f32,
-    /// The depth of the height map.
-    pub height_depth: f32,
-    /// In how many layers to split the height maps for Steep parallax mapping.
+pub use uniform::ParallaxMaterialUniform;
+
+// encase's `ShaderType` derive generates `check` functions next to the struct,
+// that recent rustc flags as unused.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::{math::Mat3, prelude::Vec4, render::render_resource::ShaderType};
+
+    /// The GPU representation of the uniform data of a [`ParallaxMaterial`].
     ///
-    /// If your `height_depth` is >0.1 and you are seeing jaggy edges,
-    /// increase this value. However, this incures a performance cost.
-    pub max_height_layers: f32,
+    /// [`ParallaxMaterial`]: crate::ParallaxMaterial
+    #[derive(Clone, Default, ShaderType)]
+    pub struct ParallaxMaterialUniform {
+        /// Doubles as diffuse albedo for non-metallic, specular for metallic and a mix for everything
+        /// in between.
+        pub base_color: Vec4,
+        /// Use a color for user friendliness even though we technically don't use the alpha channel
+        /// Might be used in the future for exposure correction in HDR
+        pub emissive: Vec4,
+        /// Linear perceptual roughness, clamped to [0.089, 1.0] in the shader
+        /// Defaults to minimum of 0.089
+        pub roughness: f32,
+        /// From [0.0, 1.0], dielectric to pure metallic
+        pub metallic: f32,
+        /// Specular intensity for non-metals on a linear scale of [0.0, 1.0]
+        /// defaults to 0.5 which is mapped to 4% reflectance in the shader
+        pub reflectance: f32,
+        /// The shader flags.
+        pub flags: u32,
+        /// When the alpha mode mask flag is set, any base color alpha above this cutoff means fully opaque,
+        /// and any below means fully transparent.
+        pub alpha_cutoff: f32,
+        /// The depth of the height map.
+        pub height_depth: f32,
+        /// In how many layers to split the height maps for Steep parallax mapping.
+        ///
+        /// If your `height_depth` is >0.1 and you are seeing jaggy edges,
+        /// increase this value. However, this incures a performance cost.
+        pub max_height_layers: f32,
+        /// The UV rectangle used by [`Silhouette`], as `(min.x, min.y, max.x, max.y)`.
+        ///
+        /// [`Silhouette`]: crate::Silhouette
+        pub silhouette_rect: Vec4,
+        /// [`DistanceLod::near`].
+        ///
+        /// [`DistanceLod::near`]: crate::DistanceLod::near
+        pub lod_near: f32,
+        /// [`DistanceLod::far`].
+        ///
+        /// [`DistanceLod::far`]: crate::DistanceLod::far
+        pub lod_far: f32,
+        /// [`DistanceLod::min_height_layers`].
+        ///
+        /// [`DistanceLod::min_height_layers`]: crate::DistanceLod::min_height_layers
+        pub lod_min_height_layers: f32,
+        /// How many refinement iterations to run after finding the height map surface.
+        pub refinement_steps: u32,
+        /// [`ParallaxMaterial::height_mid_level`].
+        ///
+        /// [`ParallaxMaterial::height_mid_level`]: crate::ParallaxMaterial::height_mid_level
+        pub height_mid_level: f32,
+        /// [`ParallaxMaterial::uv_transform`].
+        ///
+        /// [`ParallaxMaterial::uv_transform`]: crate::ParallaxMaterial::uv_transform
+        pub uv_transform: Mat3,
+        /// The transform of [`ParallaxMaterial::height_map_uv`].
+        ///
+        /// [`ParallaxMaterial::height_map_uv`]: crate::ParallaxMaterial::height_map_uv
+        pub height_uv_transform: Mat3,
+    }
 }
 
 impl AsBindGroupShaderType<ParallaxMaterialUniform> for ParallaxMaterial {
     fn as_bind_group_shader_type(&self, images: &RenderAssets<Image>) -> ParallaxMaterialUniform {
+        let lod = self.distance_lod.unwrap_or_default();
         let standard_material: StandardMaterial = self.into();
         let standard_uniform: StandardMaterialUniform =
             standard_material.as_bind_group_shader_type(images);
@@ -114,6 +344,16 @@ impl AsBindGroupShaderType<ParallaxMaterialUniform> for ParallaxMaterial {
             alpha_cutoff: standard_uniform.alpha_cutoff,
             height_depth: self.height_depth,
             max_height_layers: self.max_height_layers,
+            silhouette_rect: self.silhouette.rect().map_or(Vec4::ZERO, |rect| {
+                Vec4::new(rect.min.x, rect.min.y, rect.max.x, rect.max.y)
+            }),
+            lod_near: lod.near,
+            lod_far: lod.far,
+            lod_min_height_layers: lod.min_height_layers,
+            refinement_steps: self.refinement_steps,
+            height_mid_level: self.height_mid_level,
+            uv_transform: self.uv_transform.into(),
+            height_uv_transform: self.height_map_uv.transform(self.uv_transform).into(),
         }
     }
 }
@@ -124,18 +364,27 @@ impl AsBindGroupShaderType<ParallaxMaterialUniform> for ParallaxMaterial {
 /// `height_map` is a greyscale image representing the height of the object at a given
 /// pixel. Works like the original [`StandardMaterial`] otherwise.
 ///
-/// **WARNING**: this material _assumes_ the mesh has tangents set. If your mesh doesn't
-/// have tangents, bad unspecified things will happen.
+/// This material needs the mesh to have tangents set. [`ParallaxMaterialPlugin`]
+/// generates them for meshes without tangents, see [`generate_missing_tangents`].
+///
+/// The depth and normal prepass also run parallax mapping, so that effects
+/// reading the prepass textures line up with the relief.
 ///
 /// [default PBR material]: StandardMaterial
+#[allow(clippy::struct_excessive_bools)]
 #[derive(AsBindGroup, Debug, Clone, TypeUuid, Reflect, FromReflect)]
 #[uuid = "5bc9c7a3-fb25-4202-b91f-bc4c7d300d82"]
 #[bind_group_data(ParallaxMaterialKey)]
 #[uniform(0, ParallaxMaterialUniform)]
 #[reflect(Default, Debug)]
+#[cfg_attr(
+    feature = "inspector",
+    derive(InspectorOptions),
+    reflect(InspectorOptions)
+)]
 pub struct ParallaxMaterial {
     /// Doubles as diffuse albedo for non-metallic, specular for metallic and a mix for everything
-    /// in between. If used together with a base_color_texture, this is factored into the final
+    /// in between. If used together with a `base_color_texture`, this is factored into the final
     /// base color as `base_color * base_color_texture_value`
     pub base_color: Color,
 
@@ -170,11 +419,13 @@ pub struct ParallaxMaterial {
     /// Defaults to minimum of 0.089
     /// If used together with a roughness/metallic texture, this is factored into the final base
     /// color as `roughness * roughness_texture_value`
+    #[cfg_attr(feature = "inspector", inspector(min = 0.089, max = 1.0, speed = 0.01))]
     pub perceptual_roughness: f32,
 
     /// From [0.0, 1.0], dielectric to pure metallic
     /// If used together with a roughness/metallic texture, this is factored into the final base
     /// color as `metallic * metallic_texture_value`
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, max = 1.0, speed = 0.01))]
     pub metallic: f32,
 
     /// A texture representing both `metallic` and `preceptual_roughness`.
@@ -187,6 +438,7 @@ pub struct ParallaxMaterial {
 
     /// Specular intensity for non-metals on a linear scale of [0.0, 1.0]
     /// defaults to 0.5 which is mapped to 4% reflectance in the shader
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, max = 1.0, speed = 0.01))]
     pub reflectance: f32,
 
     /// Used to fake the lighting of bumps and dents on a material.
@@ -265,7 +517,9 @@ pub struct ParallaxMaterial {
 
     /// The height map used for parallax mapping.
     ///
-    /// Black is the tallest, white deepest.
+    /// By default, the red channel is read, black is the tallest, white deepest.
+    /// See [`ParallaxMaterial::height_convention`] and [`ParallaxMaterial::height_channel`]
+    /// to read other height maps. Pass them to the [`bake`] functions as well.
     ///
     /// To improve performance, set your `height_map`'s [`Image::sampler_descriptor`]
     /// filter mode to `FilterMode::Nearest`, as [this paper] indicates, it improves
@@ -280,14 +534,51 @@ pub struct ParallaxMaterial {
     ///
     /// Default is 0.1, anything over that value may look very awkward.
     /// Lower value look less "deep."
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, max = 1.0, speed = 0.0025))]
     pub height_depth: f32,
 
+    /// Whether the `height_map` stores depths or heights.
+    ///
+    /// Default is [`HeightConvention::Depth`], black is the tallest.
+    pub height_convention: HeightConvention,
+
+    /// Which channel of the `height_map` to read.
+    ///
+    /// With [`HeightChannel::A`], the height map can be packed in the alpha
+    /// channel of another texture, such as the normal map.
+    ///
+    /// Default is [`HeightChannel::R`].
+    pub height_channel: HeightChannel,
+
+    /// Where the mesh surface sits in the relief, as a depth from `0.0`,
+    /// the top of the relief, to `1.0`, its bottom.
+    ///
+    /// By default, the mesh surface is the top of the relief, and parallax
+    /// mapping only digs into the mesh. With `0.5`, half of the relief rises
+    /// above the mesh surface. This is a depth whatever the `height_convention`.
+    ///
+    /// Default is `0.0`.
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, max = 1.0, speed = 0.01))]
+    pub height_mid_level: f32,
+
     /// Whether to use a more accurate and more expensive algorithm.
     ///
     /// We recommend that all objects use the same [`ParallaxAlgo`], to avoid
     /// duplicating and running two shaders.
     pub algorithm: ParallaxAlgo,
 
+    /// The cone map used by [`ParallaxAlgo::ConeStepMapping`] and
+    /// [`ParallaxAlgo::RelaxedConeStepMapping`].
+    ///
+    /// Bake it from the `height_map` with [`bake::cone_map`] or
+    /// [`bake::relaxed_cone_map`], matching the `algorithm`.
+    ///
+    /// When `None`, the cone step mapping algorithms fall back to
+    /// [`ParallaxAlgo::ParallaxOcclusionMapping`].
+    #[texture(13)]
+    #[sampler(14)]
+    pub cone_map: Option<Handle<Image>>,
+
     /// In how many layers to split the height maps for Steep Parallax Mapping.
     ///
     /// If your `height_depth` is `>0.1` and you are seeing jaggy edges,
@@ -296,10 +587,105 @@ pub struct ParallaxMaterial {
     /// Default is 16.0.
     ///
     /// **This must never be less than `2.0`.**
+    #[cfg_attr(feature = "inspector", inspector(min = 2.0, max = 256.0, speed = 1.0))]
     pub max_height_layers: f32,
 
+    /// How many iterations to run to refine the result of the initial search of
+    /// the height map surface.
+    ///
+    /// Used by [`ParallaxAlgo::ReliefMapping`], [`ParallaxAlgo::RelaxedConeStepMapping`],
+    /// [`ParallaxAlgo::SecantMapping`] and [`ParallaxAlgo::IntervalMapping`].
+    /// Each iteration incures a texture sample.
+    ///
+    /// Default is 5.
+    #[cfg_attr(feature = "inspector", inspector(min = 0, max = 32))]
+    pub refinement_steps: u32,
+
+    /// Reduce the number of layers with the distance to the camera.
+    ///
+    /// Pixels far away from the camera don't need as many layers as close
+    /// ones, and past a certain distance, parallax mapping isn't noticeable
+    /// at all. See [`DistanceLod`] for details.
+    ///
+    /// Default is `None`: all pixels use up to `max_height_layers`.
+    pub distance_lod: Option<DistanceLod>,
+
+    /// Whether the height map casts shadows on itself.
+    ///
+    /// For each light affecting the material, the shader marches the height map
+    /// from the parallaxed position toward the light, and darkens the light's
+    /// contribution when the height map is in the way. The shadow is soft and
+    /// doesn't depend on the light's `shadows_enabled`.
+    ///
+    /// This runs an additional loop similar to Steep Parallax Mapping
+    /// per light, so the cost scales with the number of lights.
+    ///
+    /// Default is `false`.
+    pub self_shadowing: bool,
+
+    /// What to do with parallaxed UVs that end up outside of the mesh's UV domain.
+    ///
+    /// By default, nothing is done, and the mesh's silhouette stays flat.
+    /// See [`Silhouette`] for details.
+    pub silhouette: Silhouette,
+
+    /// Whether to write the depth of the parallaxed surface to the depth buffer.
+    ///
+    /// By default, the depth of a pixel is the depth of the flat mesh. With
+    /// `write_depth`, other meshes intersecting the material are occluded by
+    /// the relief, for example a rock pushed into a parallaxed floor.
+    ///
+    /// This works with both perspective and orthographic projections.
+    /// Note that writing depth disables early depth testing for this material.
+    ///
+    /// The depth prepass also writes the parallaxed depth. Bevy uses the same
+    /// pipeline for shadow maps, so this also enables the shadow casting half
+    /// of [`ParallaxMaterial::parallax_shadows`].
+    ///
+    /// Default is `false`.
+    pub write_depth: bool,
+
+    /// Whether shadows follow the relief of the height map.
+    ///
+    /// By default, shadow maps are rendered from the flat mesh, and the material
+    /// receives shadows on its flat surface. With `parallax_shadows`, the shadow
+    /// pass marches the height map from each light's point of view and writes
+    /// the parallaxed depth to the shadow map. The material also looks up
+    /// shadows at the parallaxed position.
+    ///
+    /// This runs parallax mapping in every shadow view the mesh is visible in,
+    /// so it can get expensive with many shadow-casting lights. You may need to
+    /// increase the lights' `shadow_depth_bias` to avoid shadow acne.
+    ///
+    /// Note that the camera's depth-only prepass also writes the parallaxed
+    /// depth, since bevy uses the same pipeline for it and shadow maps.
+    ///
+    /// Default is `false`.
+    pub parallax_shadows: bool,
+
     /// Whether to enable fog for this material
     pub fog_enabled: bool,
+
+    /// Transform applied to the mesh UVs before sampling any texture.
+    ///
+    /// Use it to tile, offset or rotate the textures without changing the mesh,
+    /// for example `Affine2::from_scale(Vec2::splat(4.0))` repeats the textures
+    /// 4 times on each axis. Parallax mapping follows the transform: the relief
+    /// keeps the same depth whatever the tiling, and normal maps are rotated
+    /// with the textures.
+    ///
+    /// Note that the [`Silhouette`] rectangle is in the transformed UV space.
+    ///
+    /// Default is [`Affine2::IDENTITY`].
+    pub uv_transform: Affine2,
+
+    /// Which UVs to sample the `height_map` and `cone_map` with.
+    ///
+    /// By default, the height map is sampled like the other textures. With a
+    /// separate mapping, a large scale height map can be combined with tiled
+    /// detail textures: the displacement found in the height map is carried over
+    /// to the other textures. See [`HeightMapUv`] for details.
+    pub height_map_uv: HeightMapUv,
 }
 
 /// The algorithm to use beyond the initial Steep parallax mapping
@@ -307,15 +693,278 @@ pub struct ParallaxMaterial {
 ///
 /// See the shader code for implementation details and explanation
 /// of the methods used.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Reflect, FromReflect)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Reflect, FromReflect, Deserialize)]
 #[reflect(Default, Debug)]
 pub enum ParallaxAlgo {
     /// A simple linear interpolation, consists of a single texture sample.
     #[default]
     ParallaxOcclusionMapping,
-    /// An iterative discovery of up to 5 iteration of the best displacement
-    /// value. Each iteration incures a texture sample.
+    /// An iterative discovery of up to [`ParallaxMaterial::refinement_steps`]
+    /// iteration of the best displacement value, using binary search.
+    /// Each iteration incures a texture sample.
     ReliefMapping,
+    /// Step along the view ray using the [`ParallaxMaterial::cone_map`]
+    /// baked with [`bake::cone_map`].
+    ///
+    /// Each step lands as far as possible without crossing the height map,
+    /// so it needs much fewer steps than Steep parallax mapping.
+    /// `max_height_layers` is the maximum number of steps.
+    ConeStepMapping,
+    /// Like [`ParallaxAlgo::ConeStepMapping`], but using the wider cones baked
+    /// with [`bake::relaxed_cone_map`], refined with [`ParallaxMaterial::refinement_steps`]
+    /// iterations of binary search like [`ParallaxAlgo::ReliefMapping`].
+    ///
+    /// Needs fewer steps than [`ParallaxAlgo::ConeStepMapping`], and is more
+    /// accurate for the same number of steps.
+    RelaxedConeStepMapping,
+    /// Traverse the mip chain of the `height_map`, skipping large areas of
+    /// the height map the view ray is above of.
+    ///
+    /// The `height_map` must have a mip chain built with [`bake::max_mip_chain`],
+    /// since the mips bevy and image editors generate average the height
+    /// rather than keeping the tallest point.
+    /// `max_height_layers` is the maximum number of iterations, each iteration
+    /// moves up or down a mip level, so it needs more than the other algorithms.
+    ///
+    /// Ideal for very deep and large height maps, close-up, the height map
+    /// texels are visible, like with `FilterMode::Nearest`.
+    QuadtreeDisplacementMapping,
+    /// Like [`ParallaxAlgo::ParallaxOcclusionMapping`], but repeats the
+    /// interpolation [`ParallaxMaterial::refinement_steps`] times, with the
+    /// secant method. Each iteration incures a texture sample.
+    ///
+    /// Converges faster than [`ParallaxAlgo::ReliefMapping`] on smooth
+    /// height maps, but may pick the wrong surface on sharp ones.
+    SecantMapping,
+    /// Like [`ParallaxAlgo::SecantMapping`], but always keeps the height map
+    /// surface between the two interpolated points, which is more robust on
+    /// sharp height maps. Each iteration incures a texture sample.
+    IntervalMapping,
+    /// Offset the UV once by the height at the original UV, limiting the
+    /// offset to `height_depth`. Consists of a single texture sample.
+    ///
+    /// This is the cheapest algorithm, intended for low-end targets such as
+    /// WebGL2 and mobile. It is wrong on steep relief or at grazing angles,
+    /// so prefer a low `height_depth`. `max_height_layers` is unused.
+    OffsetLimitedParallaxMapping,
+}
+
+/// Distance-based reduction of the parallax mapping quality.
+///
+/// Between `near` and `far`, the layer count goes from `max_height_layers`
+/// down to `min_height_layers`. Close to `far`, the parallax effect fades
+/// out. Past `far`, pixels only use normal mapping, the height map is still
+/// marched with `min_height_layers`, but doesn't displace anything.
+///
+/// This is inspired by (5) in the README's "Literature" section.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect, FromReflect, Deserialize)]
+#[serde(default)]
+#[reflect(Default, Debug)]
+#[cfg_attr(
+    feature = "inspector",
+    derive(InspectorOptions),
+    reflect(InspectorOptions)
+)]
+pub struct DistanceLod {
+    /// Distance to the camera under which `max_height_layers` is used.
+    ///
+    /// Default is 2.0.
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, speed = 0.1))]
+    pub near: f32,
+    /// Distance to the camera past which the relief is flat.
+    ///
+    /// **This must be greater than `near`.** Default is 20.0.
+    #[cfg_attr(feature = "inspector", inspector(min = 0.0, speed = 0.1))]
+    pub far: f32,
+    /// How many layers to use close to `far`.
+    ///
+    /// **This must never be less than `2.0`.** Default is 4.0.
+    #[cfg_attr(feature = "inspector", inspector(min = 2.0, max = 256.0, speed = 1.0))]
+    pub min_height_layers: f32,
+}
+impl Default for DistanceLod {
+    fn default() -> Self {
+        Self {
+            near: 2.0,
+            far: 20.0,
+            min_height_layers: 4.0,
+        }
+    }
+}
+
+/// What to do when the parallaxed UV of a pixel leaves the UV domain of the mesh.
+///
+/// The edges of a parallaxed mesh typically look flat, since the mesh itself
+/// is flat. With [`Silhouette::Clip`], the relief cuts into the outline of
+/// the mesh.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Reflect, FromReflect)]
+#[reflect(Default, Debug)]
+pub enum Silhouette {
+    /// Keep the parallaxed UV as is, the textures' sampler decides what is
+    /// displayed outside of the UV domain.
+    #[default]
+    None,
+    /// Discard pixels with a parallaxed UV outside of the provided rectangle.
+    ///
+    /// Typically, `Rect::new(0.0, 0.0, 1.0, 1.0)` for a mesh with a single
+    /// texture per face, such as bevy's `shape::Cube`.
+    ///
+    /// This discards pixels, which is known to be expensive on some hardware.
+    Clip(Rect),
+    /// Wrap the parallaxed UV around the provided rectangle.
+    ///
+    /// Useful when tiling a region of a texture atlas. Note that the texture
+    /// sampler may pick a lower mip level at the wrapping point, leaving a
+    /// thin seam.
+    Wrap(Rect),
+}
+/// Which UVs to sample [`ParallaxMaterial::height_map`] with.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Reflect, FromReflect)]
+#[reflect(Default, Debug)]
+pub enum HeightMapUv {
+    /// The UVs of the other textures: the mesh's `ATTRIBUTE_UV_0`, transformed
+    /// by [`ParallaxMaterial::uv_transform`].
+    #[default]
+    Shared,
+    /// The mesh's `ATTRIBUTE_UV_0`, transformed by the provided [`Affine2`]
+    /// instead of `uv_transform`.
+    Uv0(Affine2),
+    /// The mesh's [`ATTRIBUTE_UV_1`], transformed by the provided [`Affine2`].
+    ///
+    /// Meshes without an [`ATTRIBUTE_UV_1`] are not rendered with this material.
+    /// Since meshes have no tangents for `ATTRIBUTE_UV_1`, the shader estimates
+    /// how they relate to `ATTRIBUTE_UV_0` at each pixel, so `ATTRIBUTE_UV_1`
+    /// should not have seams where `ATTRIBUTE_UV_0` doesn't.
+    Uv1(Affine2),
+}
+impl HeightMapUv {
+    /// The transform applied to the height map UVs, `uv_transform` when [`Self::Shared`].
+    const fn transform(self, uv_transform: Affine2) -> Affine2 {
+        match self {
+            Self::Shared => uv_transform,
+            Self::Uv0(transform) | Self::Uv1(transform) => transform,
+        }
+    }
+}
+
+/// How to interpret the values of [`ParallaxMaterial::height_map`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Reflect, FromReflect, Deserialize)]
+#[reflect(Default, Debug)]
+pub enum HeightConvention {
+    /// Values are depths, black is the tallest, white the deepest.
+    #[default]
+    Depth,
+    /// Values are heights, white is the tallest, black the deepest.
+    ///
+    /// This is how most tools export height maps.
+    Height,
+}
+
+/// The channel of [`ParallaxMaterial::height_map`] storing the heights.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Reflect, FromReflect, Deserialize)]
+#[reflect(Default, Debug)]
+pub enum HeightChannel {
+    /// The red channel, also used by single channel textures.
+    #[default]
+    R,
+    /// The green channel.
+    G,
+    /// The blue channel.
+    B,
+    /// The alpha channel.
+    A,
+}
+impl HeightChannel {
+    /// The shader def selecting this channel in `parallax.wgsl`.
+    const fn shader_def(self) -> Option<&'static str> {
+        match self {
+            Self::R => None,
+            Self::G => Some("HEIGHT_MAP_CHANNEL_G"),
+            Self::B => Some("HEIGHT_MAP_CHANNEL_B"),
+            Self::A => Some("HEIGHT_MAP_CHANNEL_A"),
+        }
+    }
+}
+
+impl Silhouette {
+    const fn rect(self) -> Option<Rect> {
+        match self {
+            Self::None => None,
+            Self::Clip(rect) | Self::Wrap(rect) => Some(rect),
+        }
+    }
+}
+impl ParallaxAlgo {
+    /// The algorithm the shader will use, given whether a cone map is available.
+    const fn with_cone_map(self, has_cone_map: bool) -> Self {
+        match self {
+            Self::ConeStepMapping | Self::RelaxedConeStepMapping if !has_cone_map => {
+                Self::ParallaxOcclusionMapping
+            }
+            algorithm => algorithm,
+        }
+    }
+
+    /// The shader defs selecting this algorithm in `parallax.wgsl`.
+    const fn shader_defs(self) -> &'static [&'static str] {
+        match self {
+            Self::ParallaxOcclusionMapping => &[],
+            Self::ReliefMapping => &["RELIEF_MAPPING"],
+            Self::ConeStepMapping => &["CONE_STEP_MAPPING"],
+            Self::RelaxedConeStepMapping => &["CONE_STEP_MAPPING", "RELAXED_CONE_STEP_MAPPING"],
+            Self::QuadtreeDisplacementMapping => &["QUADTREE_DISPLACEMENT_MAPPING"],
+            Self::SecantMapping => &["SECANT_MAPPING"],
+            Self::IntervalMapping => &["SECANT_MAPPING", "INTERVAL_MAPPING"],
+            Self::OffsetLimitedParallaxMapping => &["OFFSET_LIMITED_PARALLAX_MAPPING"],
+        }
+    }
+}
+impl ParallaxMaterial {
+    /// A [`ParallaxMaterial`] with the same look as `standard`, using `height_map`.
+    ///
+    /// The parallax settings are the defaults, use struct update syntax to
+    /// change them: `ParallaxMaterial { height_depth: 0.05, ..ParallaxMaterial::from_standard(standard, height_map) }`.
+    ///
+    /// Note that `standard` should have a `normal_map_texture`, otherwise the
+    /// material won't render.
+    #[must_use]
+    pub fn from_standard(standard: StandardMaterial, height_map: Handle<Image>) -> Self {
+        Self {
+            height_map,
+            ..default()
+        }
+        .with_standard(standard)
+    }
+
+    /// Replace the [`StandardMaterial`] fields of this material with those of `standard`,
+    /// keeping the parallax settings.
+    ///
+    /// If `standard` has no `normal_map_texture`, the current one is kept.
+    #[must_use]
+    pub fn with_standard(self, standard: StandardMaterial) -> Self {
+        Self {
+            base_color: standard.base_color,
+            base_color_texture: standard.base_color_texture,
+            emissive: standard.emissive,
+            emissive_texture: standard.emissive_texture,
+            perceptual_roughness: standard.perceptual_roughness,
+            metallic: standard.metallic,
+            metallic_roughness_texture: standard.metallic_roughness_texture,
+            reflectance: standard.reflectance,
+            normal_map_texture: standard
+                .normal_map_texture
+                .unwrap_or(self.normal_map_texture),
+            flip_normal_map_y: standard.flip_normal_map_y,
+            occlusion_texture: standard.occlusion_texture,
+            double_sided: standard.double_sided,
+            cull_mode: standard.cull_mode,
+            unlit: standard.unlit,
+            alpha_mode: standard.alpha_mode,
+            depth_bias: standard.depth_bias,
+            fog_enabled: standard.fog_enabled,
+            ..self
+        }
+    }
 }
 impl Default for ParallaxMaterial {
     fn default() -> Self {
@@ -338,9 +987,21 @@ impl Default for ParallaxMaterial {
             depth_bias: 0.0,
             height_map: default(),
             height_depth: 0.1,
+            height_convention: HeightConvention::Depth,
+            height_channel: HeightChannel::R,
+            height_mid_level: 0.0,
             max_height_layers: 16.0,
+            refinement_steps: 5,
             algorithm: default(),
+            cone_map: None,
+            distance_lod: None,
+            self_shadowing: false,
+            silhouette: Silhouette::None,
+            write_depth: false,
+            parallax_shadows: false,
             fog_enabled: true,
+            uv_transform: Affine2::IDENTITY,
+            height_map_uv: HeightMapUv::Shared,
         }
     }
 }
@@ -348,20 +1009,116 @@ impl Material for ParallaxMaterial {
     fn specialize(
         _pipeline: &MaterialPipeline<Self>,
         descriptor: &mut RenderPipelineDescriptor,
-        _layout: &MeshVertexBufferLayout,
+        layout: &MeshVertexBufferLayout,
         key: MaterialPipelineKey<Self>,
     ) -> Result<(), SpecializedMeshPipelineError> {
-        let defs = &mut descriptor.fragment.as_mut().unwrap().shader_defs;
-        if key.bind_group_data.relief_mapping {
-            defs.push("RELIEF_MAPPING".into());
+        let material_key = key.bind_group_data.limited();
+        let is_prepass = key
+            .mesh_key
+            .intersects(MeshPipelineKey::DEPTH_PREPASS | MeshPipelineKey::NORMAL_PREPASS);
+        // NOTE: Bevy doesn't tell apart shadow passes from the camera's depth-only
+        // prepass, so both write the parallaxed depth with `parallax_shadows`.
+        let casts_parallax_shadow = is_prepass
+            && material_key.parallax_shadows
+            && !key.mesh_key.contains(MeshPipelineKey::NORMAL_PREPASS);
+        let write_depth = material_key.write_depth || casts_parallax_shadow;
+        let uses_uv_1 = material_key.height_map_uv == HeightMapUvKey::Uv1;
+        if is_prepass {
+            let changes_depth = write_depth || material_key.silhouette == SilhouetteKey::Clip;
+            specialize_prepass(descriptor, layout, key.mesh_key, changes_depth, uses_uv_1)?;
+        } else if uses_uv_1 {
+            // NOTE: bevy's mesh pipeline uses the shader locations 0 to 6.
+            let uv_1 = layout.get_layout(&[ATTRIBUTE_UV_1.at_shader_location(7)])?;
+            descriptor.vertex.buffers[0]
+                .attributes
+                .extend(uv_1.attributes);
+        }
+        if uses_uv_1 {
+            descriptor.vertex.shader_defs.push("HEIGHT_MAP_UV_1".into());
+        }
+        let mut defs = Vec::new();
+        if !material_key.quality.parallax_mapping {
+            defs.push("PARALLAX_OFF".into());
+        }
+        let algorithm_defs = material_key.algorithm.shader_defs();
+        defs.extend(algorithm_defs.iter().map(|def| (*def).into()));
+        if material_key.height_convention == HeightConvention::Height {
+            defs.push("INVERTED_HEIGHT_MAP".into());
+        }
+        if let Some(def) = material_key.height_channel.shader_def() {
+            defs.push(def.into());
+        }
+        defs.extend(material_key.quality.shader_defs());
+        if material_key.self_shadowing {
+            defs.push("SELF_SHADOWING".into());
         }
-        descriptor.primitive.cull_mode = key.bind_group_data.cull_mode;
+        if material_key.distance_lod {
+            defs.push("DISTANCE_LOD".into());
+        }
+        if write_depth {
+            defs.push("WRITE_DEPTH".into());
+        }
+        if material_key.parallax_shadows && !is_prepass {
+            defs.push("PARALLAX_SHADOWS".into());
+        }
+        if (write_depth || material_key.parallax_shadows) && !is_prepass {
+            defs.push("PARALLAXED_POSITION".into());
+        }
+        match material_key.height_map_uv {
+            HeightMapUvKey::Shared => {}
+            HeightMapUvKey::Uv0 => defs.push("HEIGHT_MAP_UV_TRANSFORM".into()),
+            HeightMapUvKey::Uv1 => {
+                defs.push("HEIGHT_MAP_UV_TRANSFORM".into());
+                defs.push("HEIGHT_MAP_UV_1".into());
+            }
+        }
+        match material_key.silhouette {
+            SilhouetteKey::None => {}
+            SilhouetteKey::Clip => defs.push("SILHOUETTE_CLIP".into()),
+            SilhouetteKey::Wrap => defs.push("SILHOUETTE_WRAP".into()),
+        }
+        // NOTE: The prepass has no fragment shader when it only writes depth.
+        if let Some(fragment) = &mut descriptor.fragment {
+            fragment.shader_defs.extend(defs);
+        }
+        // NOTE: The `ParallaxOverride`s are bound in all passes, see `replace_draw_functions`.
+        if let Some(layout) = material_key.override_layout {
+            descriptor.layout.push(layout.0);
+        }
+        descriptor.primitive.cull_mode = material_key.cull_mode;
         if let Some(label) = &mut descriptor.label {
             *label = format!("parallax_{}", *label).into();
         }
         Ok(())
     }
 
+    #[cfg(not(feature = "debug"))]
+    fn vertex_shader() -> ShaderRef {
+        PARALLAX_VERTEX_SHADER_HANDLE.typed::<Shader>().into()
+    }
+    #[cfg(feature = "debug")]
+    fn vertex_shader() -> ShaderRef {
+        "parallax_vertex.wgsl".into()
+    }
+
+    #[cfg(not(feature = "debug"))]
+    fn prepass_vertex_shader() -> ShaderRef {
+        PARALLAX_PREPASS_SHADER_HANDLE.typed::<Shader>().into()
+    }
+    #[cfg(feature = "debug")]
+    fn prepass_vertex_shader() -> ShaderRef {
+        "parallax_prepass.wgsl".into()
+    }
+
+    #[cfg(not(feature = "debug"))]
+    fn prepass_fragment_shader() -> ShaderRef {
+        PARALLAX_PREPASS_SHADER_HANDLE.typed::<Shader>().into()
+    }
+    #[cfg(feature = "debug")]
+    fn prepass_fragment_shader() -> ShaderRef {
+        "parallax_prepass.wgsl".into()
+    }
+
     #[cfg(not(feature = "debug"))]
     fn fragment_shader() -> ShaderRef {
         PARALLAX_MAPPING_SHADER_HANDLE.typed::<Shader>().into()
@@ -382,19 +1139,179 @@ impl Material for ParallaxMaterial {
     }
 }
 
+/// Adapt bevy's prepass pipeline (also used for shadow maps) to the parallax prepass shader.
+///
+/// The parallax prepass shader needs the UVs, normals and tangents of the
+/// mesh, regardless of which prepass is enabled, and [`ATTRIBUTE_UV_1`]
+/// when the material `uses_uv_1`. When the material `changes_depth`,
+/// the depth-only prepass needs a fragment shader as well.
+///
+/// When the prepass has no fragment shader, or the mesh lacks one of those
+/// attributes (for example while its tangents are being generated), bevy's
+/// prepass shader and vertex layout are used instead, without parallax mapping.
+fn specialize_prepass(
+    descriptor: &mut RenderPipelineDescriptor,
+    layout: &MeshVertexBufferLayout,
+    mesh_key: MeshPipelineKey,
+    changes_depth: bool,
+    uses_uv_1: bool,
+) -> Result<(), SpecializedMeshPipelineError> {
+    let runs_parallax = descriptor.fragment.is_some() || changes_depth;
+    let has_attributes = layout.contains(Mesh::ATTRIBUTE_UV_0)
+        && layout.contains(Mesh::ATTRIBUTE_NORMAL)
+        && layout.contains(Mesh::ATTRIBUTE_TANGENT)
+        && (!uses_uv_1 || layout.contains(ATTRIBUTE_UV_1));
+    if !runs_parallax || !has_attributes {
+        let bevy_prepass = PREPASS_SHADER_HANDLE.typed::<Shader>();
+        descriptor.vertex.shader = bevy_prepass.clone();
+        // NOTE: bevy's prepass shader only has a fragment entry point with NORMAL_PREPASS.
+        if mesh_key.contains(MeshPipelineKey::NORMAL_PREPASS) {
+            if let Some(fragment) = &mut descriptor.fragment {
+                fragment.shader = bevy_prepass;
+            }
+        } else {
+            descriptor.fragment = None;
+        }
+        return Ok(());
+    }
+    let mut attributes = vec![
+        Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+        Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+        Mesh::ATTRIBUTE_NORMAL.at_shader_location(2),
+        Mesh::ATTRIBUTE_TANGENT.at_shader_location(3),
+    ];
+    if layout.contains(Mesh::ATTRIBUTE_JOINT_INDEX) && layout.contains(Mesh::ATTRIBUTE_JOINT_WEIGHT)
+    {
+        attributes.push(Mesh::ATTRIBUTE_JOINT_INDEX.at_shader_location(4));
+        attributes.push(Mesh::ATTRIBUTE_JOINT_WEIGHT.at_shader_location(5));
+    }
+    if uses_uv_1 {
+        attributes.push(ATTRIBUTE_UV_1.at_shader_location(6));
+    }
+    descriptor.vertex.buffers = vec![layout.get_layout(&attributes)?];
+
+    if descriptor.fragment.is_none() {
+        descriptor.fragment = Some(FragmentState {
+            shader: descriptor.vertex.shader.clone(),
+            entry_point: "fragment".into(),
+            shader_defs: descriptor.vertex.shader_defs.clone(),
+            targets: Vec::new(),
+        });
+    }
+    Ok(())
+}
+
+/// Load the shaders of the crate, the `bevy_mod_paramap::*` imports and the
+/// material shaders.
+fn load_shaders(app: &mut App) {
+    load_internal_asset!(
+        app,
+        PARALLAX_BINDINGS_SHADER_HANDLE,
+        "parallax_bindings.wgsl",
+        Shader::from_wgsl
+    );
+    load_internal_asset!(
+        app,
+        PARALLAX_SHADER_HANDLE,
+        "parallax.wgsl",
+        Shader::from_wgsl
+    );
+    load_internal_asset!(
+        app,
+        PARALLAX_FUNCTIONS_SHADER_HANDLE,
+        "parallax_functions.wgsl",
+        Shader::from_wgsl
+    );
+    load_internal_asset!(
+        app,
+        PARALLAX_EXTENSION_SHADER_HANDLE,
+        "parallax_extension.wgsl",
+        Shader::from_wgsl
+    );
+    #[cfg(not(feature = "debug"))]
+    load_internal_asset!(
+        app,
+        PARALLAX_VERTEX_SHADER_HANDLE,
+        "parallax_vertex.wgsl",
+        Shader::from_wgsl
+    );
+    #[cfg(not(feature = "debug"))]
+    load_internal_asset!(
+        app,
+        PARALLAX_MAPPING_SHADER_HANDLE,
+        "parallax_map.wgsl",
+        Shader::from_wgsl
+    );
+    #[cfg(not(feature = "debug"))]
+    load_internal_asset!(
+        app,
+        PARALLAX_STANDARD_SHADER_HANDLE,
+        "parallax_standard.wgsl",
+        Shader::from_wgsl
+    );
+    #[cfg(not(feature = "debug"))]
+    load_internal_asset!(
+        app,
+        PARALLAX_PREPASS_SHADER_HANDLE,
+        "parallax_prepass.wgsl",
+        Shader::from_wgsl
+    );
+}
 /// Add this plugin to your app to use [`ParallaxMaterial`].
 pub struct ParallaxMaterialPlugin;
 impl Plugin for ParallaxMaterialPlugin {
     fn build(&self, app: &mut App) {
-        #[cfg(not(feature = "debug"))]
-        load_internal_asset!(
-            app,
-            PARALLAX_MAPPING_SHADER_HANDLE,
-            "parallax_map.wgsl",
-            Shader::from_wgsl
-        );
-        app.add_plugin(MaterialPlugin::<ParallaxMaterial>::default());
+        load_shaders(app);
+        app.add_plugin(MaterialPlugin::<ParallaxMaterial>::default())
+            .init_asset_loader::<ParallaxMaterialLoader>()
+            .init_resource::<ParallaxRegistry>()
+            .init_resource::<ParallaxQuality>()
+            .add_plugin(ExtractResourcePlugin::<ParallaxQuality>::default())
+            .add_plugin(UniformComponentPlugin::<ParallaxOverrideUniform>::default())
+            .add_system(swap_standard_materials)
+            .add_system(generate_missing_tangents)
+            .add_system(linearize_textures)
+            .add_system(
+                generate_height_maps
+                    .run_if(resource_exists::<GenerateHeightMaps>())
+                    .after(linearize_textures),
+            )
+            .add_system(
+                generate_normal_maps
+                    .run_if(resource_exists::<GenerateNormalMaps>())
+                    .after(linearize_textures),
+            );
+        #[cfg(feature = "gltf")]
+        app.add_system(register_gltf_height_maps.before(swap_standard_materials));
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<OverrideLayout>()
+                .add_system(
+                    extract_parallax_overrides::<ParallaxMaterial>.in_schedule(ExtractSchedule),
+                )
+                .add_system(
+                    limit_prepared_materials::<ParallaxMaterial>
+                        .in_set(RenderSet::Prepare)
+                        .after(prepare_materials::<ParallaxMaterial>),
+                )
+                .add_system(
+                    bind_prepared_overrides::<ParallaxMaterial>
+                        .in_set(RenderSet::Prepare)
+                        .after(prepare_materials::<ParallaxMaterial>),
+                )
+                .add_system(queue_override_bind_group.in_set(RenderSet::Queue));
+            replace_draw_functions::<ParallaxMaterial>(&mut render_app.world);
+        }
         app.register_type::<ParallaxMaterial>()
-            .register_type::<ParallaxAlgo>();
+            .register_type::<ParallaxAlgo>()
+            .register_type::<Silhouette>()
+            .register_type::<HeightConvention>()
+            .register_type::<HeightChannel>()
+            .register_type::<HeightMapUv>()
+            .register_type::<DistanceLod>()
+            .register_type::<ParallaxQuality>()
+            .register_type::<ParallaxExtension>()
+            .register_type::<ParallaxQualityLimits>()
+            .register_type::<ParallaxOverride>();
     }
 }
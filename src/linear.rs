@@ -0,0 +1,76 @@
+//! Make sure data textures are linear, see [`linearize_textures`].
+
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::ParallaxMaterial;
+
+/// The textures of `material` storing data rather than colors.
+fn data_textures(material: &ParallaxMaterial) -> impl Iterator<Item = &Handle<Image>> {
+    let textures = [
+        Some(&material.normal_map_texture),
+        Some(&material.height_map),
+        material.metallic_roughness_texture.as_ref(),
+        material.occlusion_texture.as_ref(),
+    ];
+    textures.into_iter().flatten()
+}
+
+/// Set a linear format on the textures of [`ParallaxMaterial`]s storing data
+/// rather than colors.
+///
+/// Bevy's image loader picks an sRGB format for most images, so the GPU
+/// converts their values as if they were colors. This breaks the lighting with
+/// normal maps, and the depth of height maps. This replaces the sRGB format of
+/// the `normal_map_texture`, `height_map`, `metallic_roughness_texture` and
+/// `occlusion_texture` of all [`ParallaxMaterial`]s by its linear equivalent,
+/// for example `Rgba8UnormSrgb` becomes `Rgba8Unorm`.
+///
+/// Images are checked when they are loaded, including hot-reloading, and when
+/// a [`ParallaxMaterial`] using them is added or modified.
+#[allow(clippy::needless_pass_by_value)]
+pub fn linearize_textures(
+    mut material_events: EventReader<AssetEvent<ParallaxMaterial>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    materials: Res<Assets<ParallaxMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let mut to_check = HashSet::new();
+    for event in &mut material_events {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        if let Some(material) = materials.get(handle) {
+            to_check.extend(data_textures(material).map(Handle::clone_weak));
+        }
+    }
+    let loaded_images: HashSet<_> = image_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => Some(handle),
+            AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    if !loaded_images.is_empty() {
+        let used = materials
+            .iter()
+            .flat_map(|(_, material)| data_textures(material));
+        let loaded_used = used.filter(|handle| loaded_images.contains(handle));
+        to_check.extend(loaded_used.map(Handle::clone_weak));
+    }
+
+    for handle in &to_check {
+        let Some(format) = images
+            .get(handle)
+            .map(|image| image.texture_descriptor.format)
+        else {
+            continue;
+        };
+        let linear = format.remove_srgb_suffix();
+        if linear != format {
+            // NOTE: only mutably borrow when needed, since it triggers `AssetEvent::Modified`.
+            if let Some(image) = images.get_mut(handle) {
+                image.texture_descriptor.format = linear;
+            }
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Load [`ParallaxMaterial`]s from `.paramap.ron` files,
+//! see [`ParallaxMaterialLoader`].
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset},
+    math::Affine2,
+    prelude::*,
+    render::render_resource::Face,
+    utils::BoxedFuture,
+};
+use ron::extensions::Extensions;
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    DistanceLod, HeightChannel, HeightConvention, HeightMapUv, ParallaxAlgo, ParallaxMaterial,
+    Silhouette,
+};
+
+/// [`AlphaMode`], as written in `.paramap.ron` files.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+enum AlphaModeDef {
+    #[default]
+    Opaque,
+    Mask(f32),
+    Blend,
+    Premultiplied,
+    Add,
+    Multiply,
+}
+impl From<AlphaModeDef> for AlphaMode {
+    fn from(mode: AlphaModeDef) -> Self {
+        match mode {
+            AlphaModeDef::Opaque => Self::Opaque,
+            AlphaModeDef::Mask(cutoff) => Self::Mask(cutoff),
+            AlphaModeDef::Blend => Self::Blend,
+            AlphaModeDef::Premultiplied => Self::Premultiplied,
+            AlphaModeDef::Add => Self::Add,
+            AlphaModeDef::Multiply => Self::Multiply,
+        }
+    }
+}
+
+/// [`Face`], as written in `.paramap.ron` files.
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum FaceDef {
+    Front,
+    Back,
+}
+impl From<FaceDef> for Face {
+    fn from(face: FaceDef) -> Self {
+        match face {
+            FaceDef::Front => Self::Front,
+            FaceDef::Back => Self::Back,
+        }
+    }
+}
+
+/// [`Silhouette`], as written in `.paramap.ron` files, the rectangles
+/// being `(min_x, min_y, max_x, max_y)`.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+enum SilhouetteDef {
+    #[default]
+    None,
+    Clip((f32, f32, f32, f32)),
+    Wrap((f32, f32, f32, f32)),
+}
+impl From<SilhouetteDef> for Silhouette {
+    fn from(silhouette: SilhouetteDef) -> Self {
+        let rect = |(min_x, min_y, max_x, max_y)| Rect::new(min_x, min_y, max_x, max_y);
+        match silhouette {
+            SilhouetteDef::None => Self::None,
+            SilhouetteDef::Clip(bounds) => Self::Clip(rect(bounds)),
+            SilhouetteDef::Wrap(bounds) => Self::Wrap(rect(bounds)),
+        }
+    }
+}
+
+/// [`Affine2`], as written in `.paramap.ron` files. Missing fields are the
+/// identity.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+struct Affine2Def {
+    scale: (f32, f32),
+    angle: f32,
+    translation: (f32, f32),
+}
+impl Default for Affine2Def {
+    fn default() -> Self {
+        Self {
+            scale: (1.0, 1.0),
+            angle: 0.0,
+            translation: (0.0, 0.0),
+        }
+    }
+}
+impl From<Affine2Def> for Affine2 {
+    fn from(transform: Affine2Def) -> Self {
+        let Affine2Def {
+            scale,
+            angle,
+            translation,
+        } = transform;
+        Self::from_scale_angle_translation(scale.into(), angle, translation.into())
+    }
+}
+
+/// [`HeightMapUv`], as written in `.paramap.ron` files.
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum HeightMapUvDef {
+    Shared,
+    Uv0(Affine2Def),
+    Uv1(Affine2Def),
+}
+impl From<HeightMapUvDef> for HeightMapUv {
+    fn from(height_map_uv: HeightMapUvDef) -> Self {
+        match height_map_uv {
+            HeightMapUvDef::Shared => Self::Shared,
+            HeightMapUvDef::Uv0(transform) => Self::Uv0(transform.into()),
+            HeightMapUvDef::Uv1(transform) => Self::Uv1(transform.into()),
+        }
+    }
+}
+
+/// Deserialize a field present in the file, so that fields that are
+/// themselves options can be set to `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// The content of a `.paramap.ron` file, the fields set in the file.
+///
+/// Textures are paths relative to the `.paramap.ron` file. Fields that are
+/// options in [`ParallaxMaterial`] are `Some(None)` when set to `None`.
+#[allow(clippy::option_option)]
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct MaterialFile {
+    base_color: Option<Color>,
+    base_color_texture: Option<String>,
+    emissive: Option<Color>,
+    emissive_texture: Option<String>,
+    perceptual_roughness: Option<f32>,
+    metallic: Option<f32>,
+    metallic_roughness_texture: Option<String>,
+    reflectance: Option<f32>,
+    normal_map_texture: Option<String>,
+    flip_normal_map_y: Option<bool>,
+    occlusion_texture: Option<String>,
+    double_sided: Option<bool>,
+    #[serde(deserialize_with = "present")]
+    cull_mode: Option<Option<FaceDef>>,
+    unlit: Option<bool>,
+    alpha_mode: Option<AlphaModeDef>,
+    depth_bias: Option<f32>,
+    height_map: Option<String>,
+    height_depth: Option<f32>,
+    height_convention: Option<HeightConvention>,
+    height_channel: Option<HeightChannel>,
+    height_mid_level: Option<f32>,
+    algorithm: Option<ParallaxAlgo>,
+    cone_map: Option<String>,
+    max_height_layers: Option<f32>,
+    refinement_steps: Option<u32>,
+    #[serde(deserialize_with = "present")]
+    distance_lod: Option<Option<DistanceLod>>,
+    self_shadowing: Option<bool>,
+    silhouette: Option<SilhouetteDef>,
+    write_depth: Option<bool>,
+    parallax_shadows: Option<bool>,
+    fog_enabled: Option<bool>,
+    uv_transform: Option<Affine2Def>,
+    height_map_uv: Option<HeightMapUvDef>,
+}
+impl MaterialFile {
+    /// Parse a `.paramap.ron` file. Fields are written without `Some`, as in
+    /// [`ParallaxMaterial`].
+    fn from_bytes(bytes: &[u8]) -> ron::error::SpannedResult<Self> {
+        let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+        options.from_bytes(bytes)
+    }
+
+    /// A [`ParallaxMaterial`] with the fields set in this file, and the
+    /// default values for the others.
+    ///
+    /// `texture` gets the handle of the texture at the given path.
+    fn into_material(self, mut texture: impl FnMut(String) -> Handle<Image>) -> ParallaxMaterial {
+        let mut material = ParallaxMaterial::default();
+        let Self {
+            base_color,
+            base_color_texture,
+            emissive,
+            emissive_texture,
+            perceptual_roughness,
+            metallic,
+            metallic_roughness_texture,
+            reflectance,
+            normal_map_texture,
+            flip_normal_map_y,
+            occlusion_texture,
+            double_sided,
+            cull_mode,
+            unlit,
+            alpha_mode,
+            depth_bias,
+            height_map,
+            height_depth,
+            height_convention,
+            height_channel,
+            height_mid_level,
+            algorithm,
+            cone_map,
+            max_height_layers,
+            refinement_steps,
+            distance_lod,
+            self_shadowing,
+            silhouette,
+            write_depth,
+            parallax_shadows,
+            fog_enabled,
+            uv_transform,
+            height_map_uv,
+        } = self;
+        // Overwrite the fields set in the file, converting them with `From`.
+        macro_rules! set {
+            ($($field:ident),* $(,)?) => {
+                $(if let Some(value) = $field {
+                    material.$field = value.into();
+                })*
+            };
+        }
+        set!(
+            base_color,
+            emissive,
+            perceptual_roughness,
+            metallic,
+            reflectance,
+            flip_normal_map_y,
+            double_sided,
+            unlit,
+            alpha_mode,
+            depth_bias,
+            height_depth,
+            height_convention,
+            height_channel,
+            height_mid_level,
+            algorithm,
+            max_height_layers,
+            refinement_steps,
+            distance_lod,
+            self_shadowing,
+            silhouette,
+            write_depth,
+            parallax_shadows,
+            fog_enabled,
+            uv_transform,
+            height_map_uv,
+        );
+        if let Some(cull_mode) = cull_mode {
+            material.cull_mode = cull_mode.map(Face::from);
+        }
+        let mut texture = |path: Option<String>| path.map(&mut texture);
+        material.base_color_texture = texture(base_color_texture);
+        material.emissive_texture = texture(emissive_texture);
+        material.metallic_roughness_texture = texture(metallic_roughness_texture);
+        material.occlusion_texture = texture(occlusion_texture);
+        material.cone_map = texture(cone_map);
+        material.normal_map_texture = texture(normal_map_texture).unwrap_or_default();
+        material.height_map = texture(height_map).unwrap_or_default();
+        material
+    }
+}
+
+/// Load [`ParallaxMaterial`]s from `.paramap.ron` files.
+///
+/// A `.paramap.ron` file lists the fields of a [`ParallaxMaterial`], textures
+/// being paths relative to the file:
+///
+/// ```text
+/// (
+///     base_color_texture: Some("base_color.jpg"),
+///     normal_map_texture: Some("normal_map.jpg"),
+///     height_map: Some("height_map.png"),
+///     height_depth: 0.05,
+///     algorithm: ReliefMapping,
+///     alpha_mode: Mask(0.5),
+/// )
+/// ```
+///
+/// Missing fields keep their default value. [`Silhouette`] rectangles are
+/// written `Clip((min_x, min_y, max_x, max_y))`, and transforms
+/// `(scale: (4.0, 4.0), angle: 0.0, translation: (0.0, 0.0))`, for example
+/// `height_map_uv: Uv1((scale: (0.5, 0.5)))`.
+///
+/// The textures are loaded along the material, and editing the file
+/// hot-reloads the material when the asset server watches for changes.
+/// [`ParallaxMaterialPlugin`] registers this loader.
+///
+/// [`ParallaxMaterialPlugin`]: crate::ParallaxMaterialPlugin
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParallaxMaterialLoader;
+impl AssetLoader for ParallaxMaterialLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let file = MaterialFile::from_bytes(bytes)?;
+            let mut dependencies = Vec::new();
+            let parent = load_context.path().parent().map(ToOwned::to_owned);
+            let texture = |path: String| {
+                let path = parent
+                    .as_ref()
+                    .map_or_else(|| path.clone().into(), |p| p.join(&path));
+                let asset_path = AssetPath::new(path, None);
+                let handle = load_context.get_handle(asset_path.clone());
+                dependencies.push(asset_path);
+                handle
+            };
+            let material = file.into_material(texture);
+            load_context
+                .set_default_asset(LoadedAsset::new(material).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["paramap.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use super::*;
+
+    #[test]
+    fn cube_material_file() {
+        let bytes = include_bytes!("../assets/cube/cube.paramap.ron");
+        let file = MaterialFile::from_bytes(bytes).unwrap();
+        let mut textures = Vec::new();
+        let material = file.into_material(|path| {
+            textures.push(path);
+            Handle::weak(HandleId::random::<Image>())
+        });
+        assert_eq!(
+            textures,
+            [
+                "paramap_color.jpg",
+                "paramap_normal.jpg",
+                "paramap_bump.jpg"
+            ]
+        );
+        assert!(material.base_color_texture.is_some());
+        assert_ne!(material.normal_map_texture, Handle::default());
+        assert_ne!(material.height_map, Handle::default());
+        assert!(material.cone_map.is_none());
+        assert_eq!(material.algorithm, ParallaxAlgo::ReliefMapping);
+        assert!((material.max_height_layers - 64.0).abs() < f32::EPSILON);
+        assert!(material.self_shadowing);
+        assert!(material.parallax_shadows);
+        assert_eq!(
+            material.silhouette,
+            Silhouette::Clip(Rect::new(0.0, 0.0, 1.0, 1.0))
+        );
+        // Fields missing from the file keep their default.
+        let default = ParallaxMaterial::default();
+        assert_eq!(material.cull_mode, default.cull_mode);
+        assert_eq!(material.uv_transform, default.uv_transform);
+        assert_eq!(material.refinement_steps, default.refinement_steps);
+    }
+
+    #[test]
+    fn transforms_and_none_options() {
+        let file = "(
+            cull_mode: None,
+            distance_lod: Some((far: 10.0)),
+            uv_transform: (scale: (4.0, 2.0)),
+            height_map_uv: Uv1((translation: (0.5, 0.0))),
+        )";
+        let file = MaterialFile::from_bytes(file.as_bytes()).unwrap();
+        let material = file.into_material(|_| unreachable!());
+        assert_eq!(material.cull_mode, None);
+        let lod = material.distance_lod.unwrap();
+        assert!((lod.far - 10.0).abs() < f32::EPSILON);
+        let scale = Affine2::from_scale(Vec2::new(4.0, 2.0));
+        assert_eq!(material.uv_transform, scale);
+        let offset = Affine2::from_translation(Vec2::new(0.5, 0.0));
+        assert_eq!(material.height_map_uv, HeightMapUv::Uv1(offset));
+    }
+}
@@ -0,0 +1,374 @@
+//! Tweak the parallax mapping of individual entities, see [`ParallaxOverride`].
+
+use std::hash::{Hash, Hasher};
+
+use bevy::{
+    core_pipeline::{
+        core_3d::{AlphaMask3d, Opaque3d, Transparent3d},
+        prepass::{AlphaMask3dPrepass, Opaque3dPrepass},
+    },
+    ecs::system::{
+        lifetimeless::{Read, SRes},
+        ReadOnlySystemParam, SystemParamItem,
+    },
+    pbr::{
+        DrawMesh, DrawPrepass, RenderMaterials, SetMaterialBindGroup, SetMeshBindGroup,
+        SetMeshViewBindGroup, SetPrepassViewBindGroup, Shadow,
+    },
+    prelude::*,
+    render::{
+        extract_component::{ComponentUniforms, DynamicUniformIndex},
+        render_phase::{
+            DrawFunctions, PhaseItem, RenderCommand, RenderCommandResult, RenderCommandState,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType,
+            ShaderStages, ShaderType,
+        },
+        renderer::RenderDevice,
+        Extract,
+    },
+};
+
+use crate::ParallaxMaterial;
+
+/// Scale the parallax settings of a single entity, without changing its
+/// [`ParallaxMaterial`].
+///
+/// Entities sharing a [`ParallaxMaterial`] can each get their own relief depth
+/// and quality, while the material asset itself stays shared and editable.
+///
+/// The scales are passed to the shader in a per-entity uniform, the entity
+/// keeps its `Handle<ParallaxMaterial>`, and no material is created for it.
+///
+/// The [`ParallaxQuality`] limits apply to the scaled values, an override
+/// can't go past them.
+///
+/// [`ParallaxQuality`]: crate::ParallaxQuality
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect, FromReflect)]
+#[reflect(Component, Default, Debug)]
+pub struct ParallaxOverride {
+    /// Multiplies [`ParallaxMaterial::height_depth`].
+    pub depth_scale: f32,
+    /// Multiplies [`ParallaxMaterial::max_height_layers`] and
+    /// [`DistanceLod::min_height_layers`].
+    ///
+    /// [`DistanceLod::min_height_layers`]: crate::DistanceLod::min_height_layers
+    pub layer_scale: f32,
+}
+impl Default for ParallaxOverride {
+    fn default() -> Self {
+        Self {
+            depth_scale: 1.0,
+            layer_scale: 1.0,
+        }
+    }
+}
+impl ParallaxOverride {
+    /// A copy of `material` with this override baked in.
+    ///
+    /// Entities with `material` and this override look like entities with the
+    /// returned material and no override.
+    #[must_use]
+    pub fn apply(self, material: &ParallaxMaterial) -> ParallaxMaterial {
+        let mut material = material.clone();
+        material.height_depth *= self.depth_scale;
+        material.max_height_layers *= self.layer_scale;
+        if let Some(lod) = &mut material.distance_lod {
+            lod.min_height_layers *= self.layer_scale;
+        }
+        material
+    }
+}
+
+pub use uniform::ParallaxOverrideUniform;
+
+// encase's `ShaderType` derive generates `check` functions next to the struct,
+// that recent rustc flags as unused.
+#[allow(dead_code)]
+mod uniform {
+    use bevy::{prelude::Component, render::render_resource::ShaderType};
+
+    use super::ParallaxOverride;
+
+    /// The GPU representation of the [`ParallaxOverride`] of an entity, in
+    /// the render world.
+    #[derive(Component, Clone, Copy, PartialEq, Debug, ShaderType)]
+    pub struct ParallaxOverrideUniform {
+        pub depth_scale: f32,
+        pub layer_scale: f32,
+    }
+    impl From<ParallaxOverride> for ParallaxOverrideUniform {
+        fn from(parallax_override: ParallaxOverride) -> Self {
+            Self {
+                depth_scale: parallax_override.depth_scale,
+                layer_scale: parallax_override.layer_scale,
+            }
+        }
+    }
+}
+
+/// Extract the [`ParallaxOverride`] of the entities with an `M` material,
+/// entities without one get the default scales.
+///
+/// All of them need one, since the override bind group is part of the
+/// pipeline layout of the material.
+#[allow(clippy::needless_pass_by_value, clippy::type_complexity)]
+pub fn extract_parallax_overrides<M: Material>(
+    mut commands: Commands,
+    mut previous_len: Local<usize>,
+    query: Extract<Query<(Entity, Option<&ParallaxOverride>), With<Handle<M>>>>,
+) {
+    let mut values = Vec::with_capacity(*previous_len);
+    for (entity, parallax_override) in &query {
+        let parallax_override = parallax_override.copied().unwrap_or_default();
+        values.push((entity, ParallaxOverrideUniform::from(parallax_override)));
+    }
+    *previous_len = values.len();
+    commands.insert_or_spawn_batch(values);
+}
+
+/// The layout of the override bind group, bound at index `3`.
+///
+/// It is also part of the material pipeline keys, see [`Overridable`].
+#[derive(Resource, Clone, Debug)]
+pub struct OverrideLayout(pub BindGroupLayout);
+impl FromWorld for OverrideLayout {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        Self(
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("parallax_override_layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(ParallaxOverrideUniform::min_size()),
+                    },
+                    count: None,
+                }],
+            }),
+        )
+    }
+}
+impl PartialEq for OverrideLayout {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id() == other.0.id()
+    }
+}
+impl Eq for OverrideLayout {}
+impl Hash for OverrideLayout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.id().hash(state);
+    }
+}
+
+/// A material pipeline key holding the [`OverrideLayout`].
+pub trait Overridable {
+    /// The layout of this key, `None` until the material is prepared.
+    fn override_layout_mut(&mut self) -> &mut Option<OverrideLayout>;
+}
+
+/// Set the [`OverrideLayout`] on the keys of the prepared `M` materials, so
+/// that their pipelines bind it.
+#[allow(clippy::needless_pass_by_value)]
+pub fn bind_prepared_overrides<M: Material>(
+    layout: Res<OverrideLayout>,
+    mut materials: ResMut<RenderMaterials<M>>,
+) where
+    M::Data: Overridable,
+{
+    for material in materials.0.values_mut() {
+        let material_layout = material.key.override_layout_mut();
+        if material_layout.is_none() {
+            *material_layout = Some(layout.clone());
+        }
+    }
+}
+
+/// The bind group of the [`ParallaxOverrideUniform`]s of this frame.
+#[derive(Resource)]
+pub struct OverrideBindGroup(BindGroup);
+
+/// Create the [`OverrideBindGroup`] of this frame.
+#[allow(clippy::needless_pass_by_value)]
+pub fn queue_override_bind_group(
+    mut commands: Commands,
+    layout: Res<OverrideLayout>,
+    render_device: Res<RenderDevice>,
+    uniforms: Res<ComponentUniforms<ParallaxOverrideUniform>>,
+) {
+    if let Some(binding) = uniforms.uniforms().binding() {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: binding,
+            }],
+            label: Some("parallax_override_bind_group"),
+            layout: &layout.0,
+        });
+        commands.insert_resource(OverrideBindGroup(bind_group));
+    }
+}
+
+/// Sets the [`OverrideBindGroup`] at the `I` index, with the offset of the
+/// entity's [`ParallaxOverrideUniform`].
+pub struct SetOverrideBindGroup<const I: usize>;
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetOverrideBindGroup<I> {
+    type Param = Option<SRes<OverrideBindGroup>>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = Option<Read<DynamicUniformIndex<ParallaxOverrideUniform>>>;
+
+    #[inline]
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        index: Option<&'w DynamicUniformIndex<ParallaxOverrideUniform>>,
+        bind_group: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (Some(index), Some(bind_group)) = (index, bind_group) else {
+            return RenderCommandResult::Failure;
+        };
+        pass.set_bind_group(I, &bind_group.into_inner().0, &[index.index()]);
+        RenderCommandResult::Success
+    }
+}
+
+/// The draw function bevy registers for materials, see [`MaterialPlugin`].
+pub type DrawMaterial<M> = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMaterialBindGroup<M, 1>,
+    SetMeshBindGroup<2>,
+    DrawMesh,
+);
+
+/// [`DrawMaterial`], also binding the [`OverrideBindGroup`].
+type DrawOverridden<M> = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMaterialBindGroup<M, 1>,
+    SetMeshBindGroup<2>,
+    SetOverrideBindGroup<3>,
+    DrawMesh,
+);
+
+/// [`DrawPrepass`], also binding the [`OverrideBindGroup`].
+type DrawOverriddenPrepass<M> = (
+    SetItemPipeline,
+    SetPrepassViewBindGroup<0>,
+    SetMaterialBindGroup<M, 1>,
+    SetMeshBindGroup<2>,
+    SetOverrideBindGroup<3>,
+    DrawMesh,
+);
+
+/// Replace the `Bevy` draw function of the `P` phase by `C`.
+///
+/// Bevy looks draw functions up by type when queuing the phase items, so
+/// this swaps the draw function of all the `P` items of a material.
+pub fn replace_draw_function<P, Bevy, C>(world: &mut World)
+where
+    P: PhaseItem,
+    Bevy: 'static,
+    C: RenderCommand<P> + Send + Sync + 'static,
+    C::Param: ReadOnlySystemParam,
+{
+    let draw_function = RenderCommandState::<P, C>::new(world);
+    // NOTE: The prepass draw functions only exist when the prepass is enabled.
+    if let Some(draw_functions) = world.get_resource::<DrawFunctions<P>>() {
+        draw_functions.write().add_with::<Bevy, _>(draw_function);
+    }
+}
+
+/// Replace the draw functions of the `M` material by ones binding the
+/// [`OverrideBindGroup`], in all the phases bevy draws materials in.
+///
+/// Must run after the [`MaterialPlugin`] of `M` is built.
+pub fn replace_draw_functions<M: Material>(render_world: &mut World) {
+    replace_draw_function::<Opaque3d, DrawMaterial<M>, DrawOverridden<M>>(render_world);
+    replace_draw_function::<AlphaMask3d, DrawMaterial<M>, DrawOverridden<M>>(render_world);
+    replace_draw_function::<Transparent3d, DrawMaterial<M>, DrawOverridden<M>>(render_world);
+    replace_draw_function::<Shadow, DrawPrepass<M>, DrawOverriddenPrepass<M>>(render_world);
+    replace_draw_function::<Opaque3dPrepass, DrawPrepass<M>, DrawOverriddenPrepass<M>>(
+        render_world,
+    );
+    replace_draw_function::<AlphaMask3dPrepass, DrawPrepass<M>, DrawOverriddenPrepass<M>>(
+        render_world,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        asset::HandleId,
+        render::{render_resource::encase::UniformBuffer, MainWorld},
+    };
+
+    use super::*;
+    use crate::DistanceLod;
+
+    #[test]
+    fn override_scales_depth_and_layers() {
+        let material = ParallaxMaterial {
+            height_depth: 0.1,
+            max_height_layers: 16.0,
+            distance_lod: Some(DistanceLod {
+                min_height_layers: 4.0,
+                ..default()
+            }),
+            ..default()
+        };
+        let parallax_override = ParallaxOverride {
+            depth_scale: 2.5,
+            layer_scale: 0.5,
+        };
+        let variant = parallax_override.apply(&material);
+        assert!((variant.height_depth - 0.25).abs() < f32::EPSILON);
+        assert!((variant.max_height_layers - 8.0).abs() < f32::EPSILON);
+        let lod = variant.distance_lod.unwrap();
+        assert!((lod.min_height_layers - 2.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn override_reaches_uniform_and_keeps_handle() {
+        let mut main_world = MainWorld::default();
+        let material = Handle::<ParallaxMaterial>::weak(HandleId::random::<ParallaxMaterial>());
+        let parallax_override = ParallaxOverride {
+            depth_scale: 2.5,
+            layer_scale: 0.5,
+        };
+        let overridden = main_world.spawn((material.clone(), parallax_override)).id();
+        let plain = main_world.spawn(material.clone()).id();
+
+        let mut render_world = World::new();
+        render_world.insert_resource(main_world);
+        let mut extract = IntoSystem::into_system(extract_parallax_overrides::<ParallaxMaterial>);
+        extract.initialize(&mut render_world);
+        extract.run((), &mut render_world);
+        extract.apply_buffers(&mut render_world);
+
+        let main_world = render_world.resource::<MainWorld>();
+        for entity in [overridden, plain] {
+            let handle = main_world.get::<Handle<ParallaxMaterial>>(entity);
+            assert_eq!(handle, Some(&material));
+        }
+        let uniform = render_world.get::<ParallaxOverrideUniform>(overridden);
+        assert_eq!(uniform, Some(&parallax_override.into()));
+        let uniform = render_world.get::<ParallaxOverrideUniform>(plain);
+        assert_eq!(uniform, Some(&ParallaxOverride::default().into()));
+
+        // The layout of the `ParallaxOverride` struct of `parallax_bindings.wgsl`.
+        let mut buffer = UniformBuffer::new(Vec::new());
+        buffer
+            .write(&ParallaxOverrideUniform::from(parallax_override))
+            .unwrap();
+        let expected = [2.5_f32.to_le_bytes(), 0.5_f32.to_le_bytes()].concat();
+        assert_eq!(buffer.into_inner()[..8], expected);
+    }
+}
@@ -0,0 +1,428 @@
+#define_import_path bevy_mod_paramap::parallax
+
+// The parallax mapping functions, usable from any shader.
+//
+// The textures are passed as parameters, so that custom materials can call
+// them with their own bindings. Which algorithm `parallaxed_uv` uses depends
+// on the shader defs, as set by `ParallaxAlgo`:
+// - RELIEF_MAPPING
+// - SECANT_MAPPING, and additionally INTERVAL_MAPPING
+// - QUADTREE_DISPLACEMENT_MAPPING
+// - OFFSET_LIMITED_PARALLAX_MAPPING
+// - CONE_STEP_MAPPING, and additionally RELAXED_CONE_STEP_MAPPING, which
+//   need a cone map, so use `cone_stepped_uv` instead of `parallaxed_uv`.
+// Without any of those, `parallaxed_uv` uses parallax occlusion mapping.
+//
+// How height maps are read also depends on shader defs, as set by
+// `HeightConvention` and `HeightChannel`:
+// - INVERTED_HEIGHT_MAP, when white is the top
+// - HEIGHT_MAP_CHANNEL_G, HEIGHT_MAP_CHANNEL_B or HEIGHT_MAP_CHANNEL_A, to read
+//   another channel than red.
+//
+// `ParallaxQuality` caps the layer count and refinement steps with the
+// MAX_HEIGHT_LAYERS_CAP and REFINEMENT_STEPS_CAP shader defs, pass the
+// material's values through `quality_layer_count` and `quality_refinement_steps`.
+
+// `layer_count`, capped by `ParallaxQuality`.
+fn quality_layer_count(layer_count: f32) -> f32 {
+#ifdef MAX_HEIGHT_LAYERS_CAP
+    // NOTE: Shader defs can't be floats, so the cap is passed as its bits.
+    return min(layer_count, bitcast<f32>(#{MAX_HEIGHT_LAYERS_CAP}u));
+#else
+    return layer_count;
+#endif
+}
+
+// `refinement_steps`, capped by `ParallaxQuality`.
+fn quality_refinement_steps(refinement_steps: u32) -> u32 {
+#ifdef REFINEMENT_STEPS_CAP
+    return min(refinement_steps, #{REFINEMENT_STEPS_CAP}u);
+#else
+    return refinement_steps;
+#endif
+}
+
+// The depth stored in a height map texel, 0 is the top and 1 the bottom.
+fn texel_depth(texel: vec4<f32>) -> f32 {
+    var value = texel.r;
+#ifdef HEIGHT_MAP_CHANNEL_G
+    value = texel.g;
+#endif
+#ifdef HEIGHT_MAP_CHANNEL_B
+    value = texel.b;
+#endif
+#ifdef HEIGHT_MAP_CHANNEL_A
+    value = texel.a;
+#endif
+#ifdef INVERTED_HEIGHT_MAP
+    value = 1.0 - value;
+#endif
+    return value;
+}
+
+// The depth of the height map at `uv`, 0 is the top and 1 the bottom.
+fn sample_height(height_map: texture_2d<f32>, height_sampler: sampler, uv: vec2<f32>) -> f32 {
+    return texel_depth(textureSample(height_map, height_sampler, uv));
+}
+
+// The UV where the view ray enters the top of the relief, when the mesh
+// surface is `mid_level` deep in it (0 is the top and 1 the bottom).
+//
+// Pass it as `original_uv` to `parallaxed_uv` or `cone_stepped_uv`, they then
+// return a depth relative to the top of the relief.
+fn reference_plane_uv(depth: f32, mid_level: f32, uv: vec2<f32>, V: vec3<f32>) -> vec2<f32> {
+    return uv + mid_level * depth * V.xy / V.z;
+}
+
+#ifdef CONE_STEP_MAPPING
+// The ratio between the radius and height of the empty cone above `uv`.
+// The cone map stores its square root, see `bake::cone_map`.
+fn sample_cone_ratio(cone_map: texture_2d<f32>, cone_sampler: sampler, uv: vec2<f32>) -> f32 {
+    let sqrt_ratio = textureSample(cone_map, cone_sampler, uv).r;
+    return sqrt_ratio * sqrt_ratio;
+}
+
+// Cone step mapping, see https://web.archive.org/web/20160311154423/http://www.lonesock.net/files/ConeStepMapping.pdf
+// and for the relaxed variant:
+// https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-18-relaxed-cone-stepping-relief-mapping
+//
+// Same parameters and return value as `parallaxed_uv`, with the cone map baked
+// from `height_map`. `max_layer_count` is the maximum number of steps.
+fn cone_stepped_uv(
+    height_map: texture_2d<f32>,
+    height_sampler: sampler,
+    cone_map: texture_2d<f32>,
+    cone_sampler: sampler,
+    depth: f32,
+    max_layer_count: f32,
+    refinement_steps: u32,
+    original_uv: vec2<f32>,
+    V: vec3<f32>,
+) -> vec3<f32> {
+    // The ray, with `z` the depth bellow the mesh surface, one unit of `z`
+    // being `depth` deep.
+    let ray = vec3<f32>(-depth * V.xy / V.z, 1.0);
+    // How far the ray goes horizontally for one unit of `z`.
+    let ray_slope = length(ray.xy);
+    let step_count = i32(max_layer_count);
+
+    var position = vec3<f32>(original_uv, 0.0);
+    var previous_position = position;
+    for (var i: i32 = 0; i < step_count; i++) {
+        let height = sample_height(height_map, height_sampler, position.xy);
+        if (position.z >= height) {
+            break;
+        }
+        // Step to where the ray exits the cone bellow `position`.
+        let cone_ratio = sample_cone_ratio(cone_map, cone_sampler, position.xy);
+        let step = cone_ratio * (height - position.z) / (ray_slope + cone_ratio);
+        previous_position = position;
+        position += ray * step;
+    }
+
+#ifdef RELAXED_CONE_STEP_MAPPING
+    // Relaxed cones may contain the surface, so the last step may end up bellow it,
+    // binary search between the last two positions for the surface.
+    for (var i = 0u; i < refinement_steps; i++) {
+        let middle = (position + previous_position) / 2.0;
+        if (middle.z >= sample_height(height_map, height_sampler, middle.xy)) {
+            position = middle;
+        } else {
+            previous_position = middle;
+        }
+    }
+#endif
+    return position;
+}
+#endif
+
+#ifdef QUADTREE_DISPLACEMENT_MAPPING
+// The cell containing `uv` in a height map mip level of `cell_count` texels.
+fn quadtree_cell(uv: vec2<f32>, cell_count: vec2<f32>) -> vec2<f32> {
+    return floor(uv * cell_count);
+}
+
+// Quadtree displacement mapping, see (2) in the README's "Literature" section.
+//
+// Each texel of a mip level of the height map stores the shallowest depth of the
+// texels it covers in the previous level (see `bake::max_mip_chain`). The ray
+// skips whole cells it is above of, and descends into the finer levels otherwise.
+//
+// Same parameters and return value as `parallaxed_uv`, `max_layer_count`
+// is the maximum number of iterations. `height_map` must have a mip chain
+// built with `bake::max_mip_chain`.
+fn quadtree_displaced_uv(
+    height_map: texture_2d<f32>,
+    depth: f32,
+    max_layer_count: f32,
+    original_uv: vec2<f32>,
+    V: vec3<f32>,
+) -> vec3<f32> {
+    // The ray, with `z` the depth bellow the mesh surface, one unit of `z`
+    // being `depth` deep.
+    let ray = vec3<f32>(-depth * V.xy / V.z, 1.0);
+    // Avoids dividing by zero when the ray is parallel to a cell border.
+    let ray_xy = select(ray.xy, vec2<f32>(0.000001), abs(ray.xy) < vec2<f32>(0.000001));
+    let iteration_count = i32(max_layer_count);
+    let max_level = i32(textureNumLevels(height_map)) - 1;
+
+    var level = max_level;
+    var position = vec3<f32>(original_uv, 0.0);
+    for (var i: i32 = 0; i < iteration_count && level >= 0; i++) {
+        let cell_count = vec2<f32>(textureDimensions(height_map, level));
+        let cell = quadtree_cell(position.xy, cell_count);
+        let wrapped_cell = vec2<i32>(cell - floor(cell / cell_count) * cell_count);
+        let cell_depth = texel_depth(textureLoad(height_map, wrapped_cell, level));
+
+        if (position.z < cell_depth) {
+            // The ray is above the whole cell, go down to its shallowest depth,
+            // unless the ray leaves the cell before.
+            let candidate = position + ray * (cell_depth - position.z);
+            if (any(quadtree_cell(candidate.xy, cell_count) != cell)) {
+                let exit_border = (cell + step(vec2<f32>(0.0), ray_xy)) / cell_count;
+                let exit_z = (exit_border - position.xy) / ray_xy;
+                // Slightly beyond the border, to end up in the next cell.
+                let CELL_EPSILON = 0.0001;
+                position += ray * (min(exit_z.x, exit_z.y) + CELL_EPSILON);
+                level = min(level + 1, max_level);
+                continue;
+            }
+            position = candidate;
+        }
+        level -= 1;
+    }
+    return position;
+}
+#endif
+
+#ifdef OFFSET_LIMITED_PARALLAX_MAPPING
+// Parallax mapping with offset limiting, see
+// https://web.archive.org/web/20150419215321/http://sunandblackcat.com/tipFullView.php?l=eng&topicid=28
+//
+// Offset the uv by the height at the original uv, without dividing by `V.z`,
+// so that the offset never goes further than `depth`. Only requires a single
+// lookup, but is wrong on steep relief or at grazing angles.
+//
+// Same parameters and return value as `parallaxed_uv`.
+fn offset_limited_uv(
+    height_map: texture_2d<f32>,
+    height_sampler: sampler,
+    depth: f32,
+    original_uv: vec2<f32>,
+    V: vec3<f32>,
+) -> vec3<f32> {
+    let height = sample_height(height_map, height_sampler, original_uv);
+    return vec3<f32>(original_uv - depth * height * V.xy, height);
+}
+#endif
+
+// Where the height map surface is between two points along the view ray,
+// as a `mix` weight. `next_height` and `previous_height` are how far bellow the
+// ray the surface is at each point.
+fn interpolation_weight(next_height: f32, previous_height: f32) -> f32 {
+    let difference = next_height - previous_height;
+    // NOTE: With the secant method, both points may end up at the same place.
+    return select(next_height / difference, 0.0, difference == 0.0);
+}
+
+// An implementation of parallax mapping, see https://en.wikipedia.org/wiki/Parallax_mapping
+// Code derived from: https://web.archive.org/web/20150419215321/http://sunandblackcat.com/tipFullView.php?l=eng&topicid=28
+//
+// Returns the parallaxed uv, and in the `z` component the depth bellow the
+// mesh surface, between 0 and 1.
+fn parallaxed_uv(
+    height_map: texture_2d<f32>,
+    height_sampler: sampler,
+    // How deep the height map is, in uv units
+    depth: f32,
+    max_layer_count: f32,
+    // Iterations of relief, secant and interval mapping
+    refinement_steps: u32,
+    // The original uv
+    original_uv: vec2<f32>,
+    // The vector from camera to the surface, in tangent space, see `tangent_space`
+    V: vec3<f32>,
+) -> vec3<f32> {
+#ifdef OFFSET_LIMITED_PARALLAX_MAPPING
+    return offset_limited_uv(height_map, height_sampler, depth, original_uv, V);
+#else
+#ifdef QUADTREE_DISPLACEMENT_MAPPING
+    return quadtree_displaced_uv(height_map, depth, max_layer_count, original_uv, V);
+#else
+
+    // Steep parallax mapping
+    // ======================
+    // split the height map into `layer_count` layers,
+    // When V hits the surface of object (excluding displacement),
+    // if not bellow or on surface including displacement (textureSample), then
+    // look forward (-= delta_uv) according to V and distance between hit surface and
+    // height map surface, repeat until bellow surface.
+    //
+    // where `layer_count` is selected smartly between `min_layer_count` and
+    // `max_layer_count` according to the steepness of V.
+    let MIN_LAYER_COUNT = 2.0;
+    let MAX_ITER = 1000;
+
+    // NOTE: distance to pixel is accounted for in `fragment` when DISTANCE_LOD
+    // is set, by reducing `max_layer_count`.
+    let view_steepness = abs(dot(vec3<f32>(0.0, 0.0, 1.0), V));
+    let layer_count = mix(max_layer_count, MIN_LAYER_COUNT, view_steepness);
+    let layer_height = 1.0 / layer_count;
+    var delta_uv = depth * V.xy / V.z / layer_count;
+    var uv = original_uv;
+
+    var current_layer_height = 0.0;
+    var current_height = sample_height(height_map, height_sampler, uv);
+    // In the original, this is a `while`, but
+    // this is a failsafe to avoid locking the dev's computer when they accidentally
+    // cause `current_height <= current_layer_height` to never happen.
+    for (var i: i32 = 0; i < MAX_ITER; i++)  {
+        if (current_height <= current_layer_height) {
+            break;
+        }
+        current_layer_height += layer_height;
+        uv -= delta_uv;
+        current_height = sample_height(height_map, height_sampler, uv);
+    }
+    
+#ifdef RELIEF_MAPPING
+    // Relief mapping
+    // ==============
+    // "refine" the rough result from the steep parallax mapping
+    // with a binary search between the layer selected by steep parallax
+    // and next one of point closest to height map surface.
+    // This eliminates the jaggy step artifacts from steep parallax
+    delta_uv = delta_uv / 2.0;
+    var delta_height = layer_height / 2.0;
+    uv += delta_uv;
+    current_layer_height -= delta_height;
+    for (var i = 0u; i < refinement_steps; i++) {
+        delta_uv = delta_uv / 2.0;
+        delta_height /= 2.0;
+        current_height = sample_height(height_map, height_sampler, uv);
+
+        if (current_height > current_layer_height) {
+            uv -= delta_uv;
+            current_layer_height += delta_height;
+        } else {
+            uv += delta_uv;
+            current_layer_height -= delta_height;
+        }
+    }
+#else    
+    // Parallax Occlusion mapping
+    // ==========================
+    // "refine" steep mapping simply by interpolating between the
+    // previous layer's height and the computed layer height.
+    // Only requires a single lookup, unlike relief mapping, but
+    // may incure artifacts on very steep relief.
+
+    // TODO: there is probably a way to use the sampler instead
+    // of interpolating by hand here.
+    var previous_uv = uv + delta_uv;
+    var previous_layer_height = current_layer_height - layer_height;
+    // How far bellow the ray the height map surface is, at both points.
+    var next_height = current_height - current_layer_height;
+    var previous_height = sample_height(height_map, height_sampler, previous_uv) - previous_layer_height;
+
+#ifdef SECANT_MAPPING
+    // Secant method
+    // =============
+    // Repeat the interpolation between the last two points.
+    //
+    // Interval mapping
+    // ================
+    // Repeat the interpolation, but keep the height map surface between the
+    // two points (regula falsi), this never diverges, unlike the secant method.
+    // See https://www.researchgate.net/publication/220792099_Interval_Mapping
+    for (var i = 0u; i < refinement_steps; i++) {
+        let weight = interpolation_weight(next_height, previous_height);
+        let middle_uv = mix(uv, previous_uv, weight);
+        let middle_layer_height = mix(current_layer_height, previous_layer_height, weight);
+        let middle_height = sample_height(height_map, height_sampler, middle_uv) - middle_layer_height;
+#ifdef INTERVAL_MAPPING
+        let middle_is_above = middle_height > 0.0;
+#else
+        let middle_is_above = false;
+        previous_uv = uv;
+        previous_layer_height = current_layer_height;
+        previous_height = next_height;
+#endif
+        if (middle_is_above) {
+            previous_uv = middle_uv;
+            previous_layer_height = middle_layer_height;
+            previous_height = middle_height;
+        } else {
+            uv = middle_uv;
+            current_layer_height = middle_layer_height;
+            next_height = middle_height;
+        }
+    }
+#endif
+
+    let weight = interpolation_weight(next_height, previous_height);
+
+    uv = mix(uv, previous_uv, weight);
+
+    current_layer_height = mix(current_layer_height, previous_layer_height, weight);
+#endif
+
+    return vec3<f32>(uv, current_layer_height);
+#endif
+#endif
+}
+
+// Convert `v`, a world space direction, into the tangent space used by `parallaxed_uv`.
+fn tangent_space(world_normal: vec3<f32>, world_tangent: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let tangent_v = vec3<f32>(
+        dot(v, world_tangent.xyz),
+        dot(v, -cross(world_normal, world_tangent.xyz) * sign(world_tangent.w)),
+        dot(v, world_normal),
+    );
+    return normalize(tangent_v);
+}
+
+// NOTE: This ensures that the world_normal is normalized and if
+// vertex tangents and normal maps then normal mapping may be applied.
+fn prepare_normal_parallax(
+    normal_map: texture_2d<f32>,
+    normal_sampler: sampler,
+    double_sided: bool,
+    // Only use the xy components of the normal map, like `StandardMaterial`
+    // does with `TextureFormat::Rg8Unorm` and other two-component formats.
+    two_component_normal_map: bool,
+    // Normal maps authored for DirectX require flipping the y component
+    flip_normal_map_y: bool,
+    // Applied to the xy components of the normal map, to account for transformed
+    // UVs. The identity when the normal map is sampled with the mesh UVs.
+    texture_to_tangent: mat2x2<f32>,
+    world_normal: vec3<f32>,
+    is_front: bool,
+    world_tangent: vec4<f32>,
+    uv: vec2<f32>,
+) -> vec3<f32> {
+    var N: vec3<f32> = world_normal;
+    var T: vec3<f32> = world_tangent.xyz;
+    var B: vec3<f32> = world_tangent.w * cross(N, T);
+
+    if (double_sided && !is_front) {
+        N = -N;
+        T = -T;
+        B = -B;
+    }
+    var Nt = textureSample(normal_map, normal_sampler, uv).rgb;
+    if (two_component_normal_map) {
+        // Only use the xy components and derive z for 2-component normal maps.
+        Nt = vec3<f32>(Nt.rg * 2.0 - 1.0, 0.0);
+        Nt.z = sqrt(1.0 - Nt.x * Nt.x - Nt.y * Nt.y);
+    } else {
+        Nt = Nt * 2.0 - 1.0;
+    }
+    if (flip_normal_map_y) {
+        Nt.y = -Nt.y;
+    }
+    Nt = vec3<f32>(texture_to_tangent * Nt.xy, Nt.z);
+    N = normalize(Nt.x * T + Nt.y * B + Nt.z * N);
+
+    return N;
+}
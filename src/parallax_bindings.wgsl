@@ -0,0 +1,61 @@
+#define_import_path bevy_mod_paramap::parallax_bindings
+
+struct ParallaxMaterial {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    perceptual_roughness: f32,
+    metallic: f32,
+    reflectance: f32,
+    flags: u32,
+    alpha_cutoff: f32,
+    height_depth: f32,
+    max_height_layers: f32,
+    silhouette_rect: vec4<f32>,
+    lod_near: f32,
+    lod_far: f32,
+    lod_min_height_layers: f32,
+    refinement_steps: u32,
+    height_mid_level: f32,
+    uv_transform: mat3x3<f32>,
+    height_uv_transform: mat3x3<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> p_material: ParallaxMaterial;
+@group(1) @binding(1)
+var p_base_color_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var p_base_color_sampler: sampler;
+@group(1) @binding(3)
+var p_emissive_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var p_emissive_sampler: sampler;
+@group(1) @binding(5)
+var p_metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(6)
+var p_metallic_roughness_sampler: sampler;
+@group(1) @binding(7)
+var p_occlusion_texture: texture_2d<f32>;
+@group(1) @binding(8)
+var p_occlusion_sampler: sampler;
+@group(1) @binding(9)
+var p_normal_map_texture: texture_2d<f32>;
+@group(1) @binding(10)
+var p_normal_map_sampler: sampler;
+@group(1) @binding(11)
+var p_height_map_texture: texture_2d<f32>;
+@group(1) @binding(12)
+var p_height_map_sampler: sampler;
+@group(1) @binding(13)
+var p_cone_map_texture: texture_2d<f32>;
+@group(1) @binding(14)
+var p_cone_map_sampler: sampler;
+
+// The `ParallaxOverride` of the entity.
+struct ParallaxOverride {
+    depth_scale: f32,
+    layer_scale: f32,
+};
+
+@group(3) @binding(0)
+var<uniform> p_override: ParallaxOverride;
@@ -0,0 +1,93 @@
+#define_import_path bevy_mod_paramap::parallax_extension
+
+// The hook for materials wrapped in `Parallax<M>`.
+//
+// Import this module in the material's fragment shader, after the view
+// bindings, and pass the mesh's uv through `parallax_extension_uv` before
+// sampling the material's textures. Without the PARALLAX_EXTENSION shader def,
+// the uv is returned unchanged, so the shader still works without parallax
+// mapping.
+//
+// The parallax bindings are in the bind group 3, the material keeps the
+// bind group 1.
+
+#ifdef PARALLAX_EXTENSION
+struct ParallaxExtension {
+    height_depth: f32,
+    max_height_layers: f32,
+    refinement_steps: u32,
+    height_mid_level: f32,
+};
+
+// The `ParallaxOverride` of the entity.
+struct ParallaxOverride {
+    depth_scale: f32,
+    layer_scale: f32,
+};
+
+@group(3) @binding(0)
+var<uniform> p_override: ParallaxOverride;
+@group(3) @binding(1)
+var<uniform> p_material: ParallaxExtension;
+@group(3) @binding(2)
+var p_height_map_texture: texture_2d<f32>;
+@group(3) @binding(3)
+var p_height_map_sampler: sampler;
+@group(3) @binding(4)
+var p_cone_map_texture: texture_2d<f32>;
+@group(3) @binding(5)
+var p_cone_map_sampler: sampler;
+
+#import bevy_mod_paramap::parallax
+#endif
+
+fn parallax_extension_uv(
+    world_position: vec4<f32>,
+    world_normal: vec3<f32>,
+    world_tangent: vec4<f32>,
+    uv: vec2<f32>,
+) -> vec2<f32> {
+#ifdef PARALLAX_EXTENSION
+    var V: vec3<f32>;
+    if (view.projection[3].w == 1.0) {
+        V = normalize(vec3<f32>(view.view_proj[0].z, view.view_proj[1].z, view.view_proj[2].z));
+    } else {
+        V = normalize(view.world_position.xyz - world_position.xyz);
+    }
+    let tangent_V = tangent_space(world_normal, world_tangent, V);
+    let depth = p_material.height_depth * p_override.depth_scale;
+    let layers = quality_layer_count(p_material.max_height_layers * p_override.layer_scale);
+    let original_uv = reference_plane_uv(
+        depth,
+        p_material.height_mid_level,
+        uv,
+        tangent_V,
+    );
+#ifdef CONE_STEP_MAPPING
+    let uv_3d = cone_stepped_uv(
+        p_height_map_texture,
+        p_height_map_sampler,
+        p_cone_map_texture,
+        p_cone_map_sampler,
+        depth,
+        layers,
+        quality_refinement_steps(p_material.refinement_steps),
+        original_uv,
+        tangent_V,
+    );
+#else
+    let uv_3d = parallaxed_uv(
+        p_height_map_texture,
+        p_height_map_sampler,
+        depth,
+        layers,
+        quality_refinement_steps(p_material.refinement_steps),
+        original_uv,
+        tangent_V,
+    );
+#endif
+    return uv_3d.xy;
+#else
+    return uv;
+#endif
+}
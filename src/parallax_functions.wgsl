@@ -0,0 +1,256 @@
+#define_import_path bevy_mod_paramap::parallax_functions
+
+// NOTE: Like bevy's `bevy_pbr::pbr_functions`, this module expects the
+// importing shader to already have imported the view bindings
+// (`bevy_pbr::mesh_view_bindings` or `bevy_pbr::prepass_bindings`),
+// `bevy_pbr::pbr_bindings`, `bevy_mod_paramap::parallax_bindings` and
+// `bevy_mod_paramap::parallax`.
+
+
+// Run `parallaxed_uv`, or `cone_stepped_uv` with CONE_STEP_MAPPING,
+// with the textures of p_material, starting from its reference plane.
+//
+// `height_uv` and `tangent_V` are in the UV space of the height map.
+fn material_parallaxed_uv(
+    depth: f32,
+    max_layer_count: f32,
+    height_uv: vec2<f32>,
+    tangent_V: vec3<f32>,
+) -> vec3<f32> {
+    let uv = reference_plane_uv(depth, p_material.height_mid_level, height_uv, tangent_V);
+#ifdef CONE_STEP_MAPPING
+    return cone_stepped_uv(
+        p_height_map_texture,
+        p_height_map_sampler,
+        p_cone_map_texture,
+        p_cone_map_sampler,
+        depth,
+        max_layer_count,
+        quality_refinement_steps(p_material.refinement_steps),
+        uv,
+        tangent_V,
+    );
+#else
+    return parallaxed_uv(
+        p_height_map_texture,
+        p_height_map_sampler,
+        depth,
+        max_layer_count,
+        quality_refinement_steps(p_material.refinement_steps),
+        uv,
+        tangent_V,
+    );
+#endif
+}
+
+// The UV to sample the textures of p_material with, `uv` transformed by
+// its `uv_transform`.
+fn material_uv(uv: vec2<f32>) -> vec2<f32> {
+    return (p_material.uv_transform * vec3<f32>(uv, 1.0)).xy;
+}
+
+// The linear part of p_material's `uv_transform`.
+fn material_uv_linear() -> mat2x2<f32> {
+    let transform = p_material.uv_transform;
+    return mat2x2<f32>(transform[0].xy, transform[1].xy);
+}
+
+// The inverse of `m`, the zero matrix if `m` can't be inverted.
+fn inverse_2x2(m: mat2x2<f32>) -> mat2x2<f32> {
+    let det = determinant(m);
+    if (abs(det) < 0.0000001) {
+        return mat2x2<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    return mat2x2<f32>(m[1].y, -m[0].y, -m[1].x, m[0].x) * (1.0 / det);
+}
+
+#ifdef HEIGHT_MAP_UV_TRANSFORM
+// The linear map from the mesh UVs to the height map UVs of p_material,
+// at the current pixel.
+//
+// With HEIGHT_MAP_UV_1, the mesh has no tangents for `uv_1`, so the map from
+// `uv` to `uv_1` is estimated with screen space derivatives.
+//
+// NOTE: This uses derivatives, so must be called in uniform control flow.
+fn material_height_uv_linear(uv: vec2<f32>, uv_1: vec2<f32>) -> mat2x2<f32> {
+    let transform = p_material.height_uv_transform;
+    let linear = mat2x2<f32>(transform[0].xy, transform[1].xy);
+#ifdef HEIGHT_MAP_UV_1
+    let uv_derivatives = mat2x2<f32>(dpdx(uv), dpdy(uv));
+    let uv_1_derivatives = mat2x2<f32>(dpdx(uv_1), dpdy(uv_1));
+    return linear * uv_1_derivatives * inverse_2x2(uv_derivatives);
+#else
+    return linear;
+#endif
+}
+#endif
+
+// The `texture_to_tangent` of `prepare_normal_parallax` for p_material.
+//
+// The xy of normals are slopes, so they are transformed by the transpose of
+// `material_uv_linear`. The normal map's `y` points toward `-v`, hence `flip`.
+// The scale is divided out, so that tiling doesn't change the normal map's strength.
+fn material_normal_transform() -> mat2x2<f32> {
+    let linear = material_uv_linear();
+    let flip = mat2x2<f32>(1.0, 0.0, 0.0, -1.0);
+    let scale = sqrt(max(abs(determinant(linear)), 0.000001));
+    return flip * transpose(linear) * flip * (1.0 / scale);
+}
+
+// Run `prepare_normal_parallax` with the normal map and flags of p_material.
+//
+// `uv` is a UV returned by `material_uv` or `material_parallax`.
+fn prepare_material_normal(
+    world_normal: vec3<f32>,
+    is_front: bool,
+    world_tangent: vec4<f32>,
+    uv: vec2<f32>,
+) -> vec3<f32> {
+    let flags = p_material.flags;
+    return prepare_normal_parallax(
+        p_normal_map_texture,
+        p_normal_map_sampler,
+        (flags & STANDARD_MATERIAL_FLAGS_DOUBLE_SIDED_BIT) != 0u,
+        (flags & STANDARD_MATERIAL_FLAGS_TWO_COMPONENT_NORMAL_MAP) != 0u,
+        (flags & STANDARD_MATERIAL_FLAGS_FLIP_NORMAL_MAP_Y) != 0u,
+        material_normal_transform(),
+        world_normal,
+        is_front,
+        world_tangent,
+        uv,
+    );
+}
+
+// How many world units is one unit of UV, at the current pixel.
+//
+// NOTE: This uses derivatives, so must be called in uniform control flow.
+fn world_units_per_uv(world_position: vec3<f32>, uv: vec2<f32>) -> f32 {
+    let world_area = length(cross(dpdx(world_position), dpdy(world_position)));
+    let uv_area = abs(determinant(mat2x2<f32>(dpdx(uv), dpdy(uv))));
+    return sqrt(world_area / max(uv_area, 0.000001));
+}
+
+// Move `world_position` along the view ray to where `parallaxed_uv` found
+// the height map surface.
+//
+// `parallax_depth` is the `z` component of the value returned by `material_parallaxed_uv`
+// and `depth` the `depth` parameter passed to it. The mesh surface is
+// `p_material.height_mid_level` deep in the relief.
+// `world_per_uv` is the value returned by `world_units_per_uv`.
+fn parallaxed_world_position(
+    world_position: vec4<f32>,
+    V: vec3<f32>,
+    tangent_V: vec3<f32>,
+    depth: f32,
+    parallax_depth: f32,
+    world_per_uv: f32,
+) -> vec4<f32> {
+    // The distance along the view ray, in UV units, between the mesh
+    // surface and the height map surface.
+    let mesh_depth = parallax_depth - p_material.height_mid_level;
+    let ray_length = mesh_depth * depth / max(tangent_V.z, 0.0001);
+    return vec4<f32>(world_position.xyz - V * ray_length * world_per_uv, 1.0);
+}
+
+// The depth buffer value of a world space position.
+//
+// Works with both perspective and orthographic projections, since with
+// orthographic projection, `w` is always 1.
+fn world_position_depth(world_position: vec4<f32>) -> f32 {
+    let clip_position = view.view_proj * world_position;
+    return clip_position.z / clip_position.w;
+}
+
+// The result of `material_parallax`.
+struct MaterialParallax {
+    // The parallaxed uv, transformed by `material_uv`, and in the `z` component
+    // the depth bellow the top of the relief.
+    uv_3d: vec3<f32>,
+    // The same point as `uv_3d`, in the UV space of the height map.
+    height_uv_3d: vec3<f32>,
+    // The linear map from mesh UV directions to height map UV directions.
+    height_uv_linear: mat2x2<f32>,
+    // The `depth` parameter passed to `parallaxed_uv`.
+    depth: f32,
+    // The `max_layer_count` parameter passed to `parallaxed_uv`.
+    max_layer_count: f32,
+};
+
+// Run `material_parallaxed_uv` with the `p_material` parameters, scaled by the
+// `p_override` of the entity.
+//
+// This is shared between the main pass and the prepass, so that they agree
+// on what the parallaxed surface looks like.
+//
+// The height map is marched in its own UV space, then the displacement is
+// carried over to the UVs of the other textures.
+fn material_parallax(
+    world_position: vec4<f32>,
+    // The mesh UV, before `material_uv`
+    mesh_uv: vec2<f32>,
+    // The mesh `ATTRIBUTE_UV_1`, only used with HEIGHT_MAP_UV_1
+    mesh_uv_1: vec2<f32>,
+    // The vector from camera to the surface of p_material, in tangent space
+    mesh_tangent_V: vec3<f32>,
+) -> MaterialParallax {
+    let uv = material_uv(mesh_uv);
+#ifdef HEIGHT_MAP_UV_TRANSFORM
+    let height_uv_linear = material_height_uv_linear(mesh_uv, mesh_uv_1);
+#ifdef HEIGHT_MAP_UV_1
+    let height_source_uv = mesh_uv_1;
+#else
+    let height_source_uv = mesh_uv;
+#endif
+    let height_uv = (p_material.height_uv_transform * vec3<f32>(height_source_uv, 1.0)).xy;
+#else
+    let height_uv_linear = material_uv_linear();
+    let height_uv = uv;
+#endif
+    // The `z` component is kept as is, so that `height_depth` is relative to the
+    // mesh UVs: tiling the textures doesn't change how deep the relief is.
+    let tangent_V = vec3<f32>(height_uv_linear * mesh_tangent_V.xy, mesh_tangent_V.z);
+    var depth = p_material.height_depth * p_override.depth_scale;
+    let layer_scale = p_override.layer_scale;
+    var max_layer_count = quality_layer_count(p_material.max_height_layers * layer_scale);
+#ifdef PARALLAX_OFF
+    // Plain normal mapping, set by `ParallaxQuality::Off`.
+    depth = 0.0;
+    let height_uv_3d = vec3<f32>(height_uv, 0.0);
+#else
+#ifdef DISTANCE_LOD
+    // Reduce the layer count with distance to camera, then fade out
+    // to plain normal mapping over the last quarter of the lod range.
+    // Pixels further than `lod_far` get a `depth` of 0, so the march leaves
+    // their uv as is. It still runs, as `textureSample` must be called in
+    // uniform control flow.
+    let LOD_FADE_START = 0.75;
+    let distance = length(view.world_position.xyz - world_position.xyz);
+    let lod_range = p_material.lod_far - p_material.lod_near;
+    let lod = saturate((distance - p_material.lod_near) / lod_range);
+    let min_layer_count = quality_layer_count(p_material.lod_min_height_layers * layer_scale);
+    max_layer_count = mix(max_layer_count, min_layer_count, lod);
+    depth *= 1.0 - smoothstep(LOD_FADE_START, 1.0, lod);
+#endif
+    let height_uv_3d = material_parallaxed_uv(depth, max_layer_count, height_uv, tangent_V);
+#endif
+#ifdef HEIGHT_MAP_UV_TRANSFORM
+    let mesh_offset = inverse_2x2(height_uv_linear) * (height_uv_3d.xy - height_uv);
+    var uv_3d = vec3<f32>(uv + material_uv_linear() * mesh_offset, height_uv_3d.z);
+#else
+    var uv_3d = height_uv_3d;
+#endif
+#ifdef SILHOUETTE_WRAP
+    let rect = p_material.silhouette_rect;
+    let rect_size = rect.zw - rect.xy;
+    uv_3d = vec3<f32>(rect.xy + fract((uv_3d.xy - rect.xy) / rect_size) * rect_size, uv_3d.z);
+#endif
+    return MaterialParallax(uv_3d, height_uv_3d, height_uv_linear, depth, max_layer_count);
+}
+
+#ifdef SILHOUETTE_CLIP
+// Whether `uv` is outside of the `Silhouette::Clip` rectangle.
+fn outside_silhouette(uv: vec2<f32>) -> bool {
+    let rect = p_material.silhouette_rect;
+    return any(uv < rect.xy) || any(uv > rect.zw);
+}
+#endif
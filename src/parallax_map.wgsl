@@ -10,204 +10,229 @@
 #import bevy_pbr::fog
 #import bevy_pbr::pbr_functions
 
-struct ParallaxMaterial {
-    base_color: vec4<f32>,
-    emissive: vec4<f32>,
-    perceptual_roughness: f32,
-    metallic: f32,
-    reflectance: f32,
-    flags: u32,
-    alpha_cutoff: f32,
-    height_depth: f32,
-    max_height_layers: f32,
-};
+#import bevy_mod_paramap::parallax_bindings
+#import bevy_mod_paramap::parallax
+#import bevy_mod_paramap::parallax_functions
 
 struct FragmentInput {
     @builtin(front_facing) is_front: bool,
     @builtin(position) frag_coord: vec4<f32>,
     #import bevy_pbr::mesh_vertex_output
+#ifdef HEIGHT_MAP_UV_1
+    @location(5) uv_1: vec2<f32>,
+#endif
 };
 
-@group(1) @binding(0)
-var<uniform> p_material: ParallaxMaterial;
-@group(1) @binding(1)
-var p_base_color_texture: texture_2d<f32>;
-@group(1) @binding(2)
-var p_base_color_sampler: sampler;
-@group(1) @binding(3)
-var p_emissive_texture: texture_2d<f32>;
-@group(1) @binding(4)
-var p_emissive_sampler: sampler;
-@group(1) @binding(5)
-var p_metallic_roughness_texture: texture_2d<f32>;
-@group(1) @binding(6)
-var p_metallic_roughness_sampler: sampler;
-@group(1) @binding(7)
-var p_occlusion_texture: texture_2d<f32>;
-@group(1) @binding(8)
-var p_occlusion_sampler: sampler;
-@group(1) @binding(9)
-var p_normal_map_texture: texture_2d<f32>;
-@group(1) @binding(10)
-var p_normal_map_sampler: sampler;
-@group(1) @binding(11)
-var p_height_map_texture: texture_2d<f32>;
-@group(1) @binding(12)
-var p_height_map_sampler: sampler;
-
-
-// NOTE: This ensures that the world_normal is normalized and if
-// vertex tangents and normal maps then normal mapping may be applied.
-fn prepare_normal_parallax(
-    standard_material_flags: u32,
-    world_normal: vec3<f32>,
-    is_front: bool,
-    world_tangent: vec4<f32>,
-    uv: vec2<f32>,
-) -> vec3<f32> {
-    var N: vec3<f32> = world_normal;
-    var T: vec3<f32> = world_tangent.xyz;
-    var B: vec3<f32> = world_tangent.w * cross(N, T);
-
-    if ((standard_material_flags & STANDARD_MATERIAL_FLAGS_DOUBLE_SIDED_BIT) != 0u) {
-        if (!is_front) {
-            N = -N;
-            T = -T;
-            B = -B;
-        }
-    }
-    var Nt = textureSample(p_normal_map_texture, p_normal_map_sampler, uv).rgb;
-    if ((standard_material_flags & STANDARD_MATERIAL_FLAGS_TWO_COMPONENT_NORMAL_MAP) != 0u) {
-        // Only use the xy components and derive z for 2-component normal maps.
-        Nt = vec3<f32>(Nt.rg * 2.0 - 1.0, 0.0);
-        Nt.z = sqrt(1.0 - Nt.x * Nt.x - Nt.y * Nt.y);
-    } else {
-        Nt = Nt * 2.0 - 1.0;
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+#ifdef WRITE_DEPTH
+    @builtin(frag_depth) depth: f32,
+#endif
+};
+
+#ifdef SELF_SHADOWING
+// Soft self-shadowing, see the "Self-shadowing" section of the sunandblackcat article.
+//
+// March from the parallaxed point (as returned by `material_parallax`) toward
+// the light. Each time the height map is above the ray, the light is partially
+// occluded. Returns how much of the light reaches the point, 1.0 means fully lit.
+fn parallax_self_shadow(
+    parallax: MaterialParallax,
+    // The tangent space vector from the surface of p_material to the light
+    mesh_L: vec3<f32>,
+) -> f32 {
+    let depth = parallax.depth;
+    let max_layer_count = parallax.max_layer_count;
+    let uv_3d = parallax.height_uv_3d;
+    let L = vec3<f32>(parallax.height_uv_linear * mesh_L.xy, mesh_L.z);
+    let MIN_LAYER_COUNT = 2.0;
+    let MAX_ITER = 1000;
+
+    let initial_layer_height = uv_3d.z;
+    // Light is bellow the surface (this is handled by the regular lighting code)
+    // or we are at the very top of the height map, nothing can occlude the light.
+    if (L.z <= 0.0 || initial_layer_height <= 0.0) {
+        return 1.0;
     }
-    // Normal maps authored for DirectX require flipping the y component
-    if ((standard_material_flags & STANDARD_MATERIAL_FLAGS_FLIP_NORMAL_MAP_Y) != 0u) {
-        Nt.y = -Nt.y;
+    let layer_count = mix(max_layer_count, MIN_LAYER_COUNT, abs(L.z));
+    let layer_height = initial_layer_height / layer_count;
+    // Unlike in `parallaxed_uv`, we are going up, toward the light.
+    let delta_uv = depth * layer_height * L.xy / L.z;
+
+    var uv = uv_3d.xy + delta_uv;
+    var current_layer_height = initial_layer_height - layer_height;
+    var occlusion = 0.0;
+    for (var i: i32 = 1; i < MAX_ITER; i++) {
+        if (current_layer_height <= 0.0) {
+            break;
+        }
+        let current_height = sample_height(p_height_map_texture, p_height_map_sampler, uv);
+        // The height map is above the ray, the further above it is,
+        // and the closer to the parallaxed point, the darker the shadow.
+        if (current_height < current_layer_height) {
+            let layer_weight = 1.0 - f32(i) / layer_count;
+            occlusion = max(occlusion, (current_layer_height - current_height) * layer_weight);
+        }
+        current_layer_height -= layer_height;
+        uv += delta_uv;
     }
-    N = normalize(Nt.x * T + Nt.y * B + Nt.z * N);
-
-    return N;
+    return pow(1.0 - occlusion, 4.0);
 }
 
-fn sample_height(uv: vec2<f32>) -> f32 {
-    return textureSample(p_height_map_texture, p_height_map_sampler, uv).r;
-}
+#endif
 
-// An implementation of parallax mapping, see https://en.wikipedia.org/wiki/Parallax_mapping
-// Code derived from: https://web.archive.org/web/20150419215321/http://sunandblackcat.com/tipFullView.php?l=eng&topicid=28
-fn parallaxed_uv(
-    depth: f32,
-    max_layer_count: f32,
-    // The original uv
-    original_uv: vec2<f32>,
-    // The vector from camera to the surface of p_material
-    V: vec3<f32>,
-) -> vec3<f32> {
-
-    // Steep parallax mapping
-    // ======================
-    // split the height map into `layer_count` layers,
-    // When V hits the surface of object (excluding displacement),
-    // if not bellow or on surface including displacement (textureSample), then
-    // look forward (-= delta_uv) according to V and distance between hit surface and
-    // height map surface, repeat until bellow surface.
-    //
-    // where `layer_count` is selected smartly between `min_layer_count` and
-    // `max_layer_count` according to the steepness of V.
-    let MIN_LAYER_COUNT = 2.0;
-    let MAX_ITER = 1000;
+// Bevy's `pbr` function from `bevy_pbr::pbr_functions`, with shadow maps
+// sampled at `shadow_position` and, with SELF_SHADOWING, each light's
+// contribution attenuated by `parallax_self_shadow`.
+//
+// Only the shadow lookups use `shadow_position`, the rest of the lighting
+// uses `in.world_position`.
+fn pbr_parallax(
+    in: PbrInput,
+    parallax: MaterialParallax,
+    world_tangent: vec4<f32>,
+    shadow_position: vec4<f32>,
+) -> vec4<f32> {
+    var output_color: vec4<f32> = in.material.base_color;
 
-    // TODO: account for distance to pixel
-    // (in (5), it's `1 - (V.len() * 0.09)^0.45`) * view_steepness
-    let view_steepness = abs(dot(vec3<f32>(0.0, 0.0, 1.0), V));
-    let layer_count = mix(max_layer_count, MIN_LAYER_COUNT, view_steepness);
-    let layer_height = 1.0 / layer_count;
-    var delta_uv = depth * V.xy / V.z / layer_count;
-    var uv = original_uv;
-
-    var current_layer_height = 0.0;
-    var current_height = sample_height(uv);
-    // In the original, this is a `while`, but
-    // this is a failsafe to avoid locking the dev's computer when they accidentally
-    // cause `current_height <= current_layer_height` to never happen.
-    for (var i: i32 = 0; i < MAX_ITER; i++)  {
-        if (current_height <= current_layer_height) {
-            break;
+    let emissive = in.material.emissive;
+
+    let metallic = in.material.metallic;
+    let perceptual_roughness = in.material.perceptual_roughness;
+    let roughness = perceptualRoughnessToRoughness(perceptual_roughness);
+
+    let occlusion = in.occlusion;
+
+    output_color = alpha_discard(in.material, output_color);
+
+    let NdotV = max(dot(in.N, in.V), 0.0001);
+
+    let reflectance = in.material.reflectance;
+    let F0 = 0.16 * reflectance * reflectance * (1.0 - metallic) + output_color.rgb * metallic;
+
+    let diffuse_color = output_color.rgb * (1.0 - metallic);
+
+    let R = reflect(-in.V, in.N);
+
+    let f_ab = F_AB(perceptual_roughness, NdotV);
+
+    var direct_light: vec3<f32> = vec3<f32>(0.0);
+
+    let view_z = dot(vec4<f32>(
+        view.inverse_view[0].z,
+        view.inverse_view[1].z,
+        view.inverse_view[2].z,
+        view.inverse_view[3].z
+    ), in.world_position);
+    let cluster_index = fragment_cluster_index(in.frag_coord.xy, view_z, in.is_orthographic);
+    let offset_and_counts = unpack_offset_and_counts(cluster_index);
+
+    // Point lights (direct)
+    for (var i: u32 = offset_and_counts[0]; i < offset_and_counts[0] + offset_and_counts[1]; i = i + 1u) {
+        let light_id = get_light_id(i);
+        var shadow: f32 = 1.0;
+        if ((in.flags & MESH_FLAGS_SHADOW_RECEIVER_BIT) != 0u
+                && (point_lights.data[light_id].flags & POINT_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_point_shadow(light_id, shadow_position, in.world_normal);
         }
-        current_layer_height += layer_height;
-        uv -= delta_uv;
-        current_height = sample_height(uv);
+        let to_light = point_lights.data[light_id].position_radius.xyz - in.world_position.xyz;
+        let L = tangent_space(in.world_normal, world_tangent, normalize(to_light));
+#ifdef SELF_SHADOWING
+        shadow *= parallax_self_shadow(parallax, L);
+#endif
+        let light_contrib = point_light(in.world_position.xyz, light_id, roughness, NdotV, in.N, in.V, R, F0, f_ab, diffuse_color);
+        direct_light += light_contrib * shadow;
     }
-    
-#ifdef RELIEF_MAPPING
-    // Relief mapping
-    // ==============
-    // "refine" the rough result from the steep parallax mapping
-    // with a binary search between the layer selected by steep parallax
-    // and next one of point closest to height map surface.
-    // This eliminates the jaggy step artifacts from steep parallax
-    let MAX_STEPS: i32 = 5;
-
-    delta_uv = delta_uv / 2.0;
-    var delta_height = layer_height / 2.0;
-    uv += delta_uv;
-    current_layer_height -= delta_height;
-    for (var i: i32 = 0; i < MAX_STEPS; i++) {
-        delta_uv = delta_uv / 2.0;
-        delta_height /= 2.0;
-        current_height = sample_height(uv);
-
-        if (current_height > current_layer_height) {
-            uv -= delta_uv;
-            current_layer_height += delta_height;
-        } else {
-            uv += delta_uv;
-            current_layer_height -= delta_height;
+
+    // Spot lights (direct)
+    for (var i: u32 = offset_and_counts[0] + offset_and_counts[1]; i < offset_and_counts[0] + offset_and_counts[1] + offset_and_counts[2]; i = i + 1u) {
+        let light_id = get_light_id(i);
+        var shadow: f32 = 1.0;
+        if ((in.flags & MESH_FLAGS_SHADOW_RECEIVER_BIT) != 0u
+                && (point_lights.data[light_id].flags & POINT_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_spot_shadow(light_id, shadow_position, in.world_normal);
         }
+        let to_light = point_lights.data[light_id].position_radius.xyz - in.world_position.xyz;
+        let L = tangent_space(in.world_normal, world_tangent, normalize(to_light));
+#ifdef SELF_SHADOWING
+        shadow *= parallax_self_shadow(parallax, L);
+#endif
+        let light_contrib = spot_light(in.world_position.xyz, light_id, roughness, NdotV, in.N, in.V, R, F0, f_ab, diffuse_color);
+        direct_light += light_contrib * shadow;
     }
-#else    
-    // Parallax Occlusion mapping
-    // ==========================
-    // "refine" steep mapping simply by interpolating between the
-    // previous layer's height and the computed layer height.
-    // Only requires a single lookup, unlike relief mapping, but
-    // may incure artifacts on very steep relief.
-
-    // TODO: there is probably a way to use the sampler instead
-    // of interpolating by hand here.
-    let previous_uv = uv + delta_uv;
-    let next_height = current_height - current_layer_height;
-    let previous_height = sample_height(previous_uv) - current_layer_height + layer_height;
-
-    let weight = next_height / (next_height - previous_height);
-
-    uv = mix(uv, previous_uv, weight);
-
-    current_layer_height = current_layer_height
-        + mix(next_height, previous_height, weight);
+
+    // Directional lights (direct)
+    let n_directional_lights = lights.n_directional_lights;
+    for (var i: u32 = 0u; i < n_directional_lights; i = i + 1u) {
+        var shadow: f32 = 1.0;
+        if ((in.flags & MESH_FLAGS_SHADOW_RECEIVER_BIT) != 0u
+                && (lights.directional_lights[i].flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_directional_shadow(i, shadow_position, in.world_normal, view_z);
+        }
+        let L = tangent_space(in.world_normal, world_tangent, lights.directional_lights[i].direction_to_light);
+#ifdef SELF_SHADOWING
+        shadow *= parallax_self_shadow(parallax, L);
+#endif
+        var light_contrib = directional_light(i, roughness, NdotV, in.N, in.V, R, F0, f_ab, diffuse_color);
+#ifdef DIRECTIONAL_LIGHT_SHADOW_MAP_DEBUG_CASCADES
+        light_contrib = cascade_debug_visualization(light_contrib, i, view_z);
 #endif
+        direct_light += light_contrib * shadow;
+    }
 
-    return vec3<f32>(uv, current_layer_height);
-}
+    // Ambient light (indirect)
+    var indirect_light = ambient_light(in.world_position, in.N, in.V, NdotV, diffuse_color, F0, perceptual_roughness, occlusion);
+
+    // Environment map light (indirect)
+#ifdef ENVIRONMENT_MAP
+    let environment_light = environment_map_light(perceptual_roughness, roughness, diffuse_color, NdotV, f_ab, in.N, R, F0);
+    indirect_light += (environment_light.diffuse * occlusion) + environment_light.specular;
+#endif
+
+    let emissive_light = emissive.rgb * output_color.a;
+
+    output_color = vec4<f32>(
+        direct_light + indirect_light + emissive_light,
+        output_color.a
+    );
 
+    output_color = cluster_debug_visualization(
+        output_color,
+        view_z,
+        in.is_orthographic,
+        offset_and_counts,
+        cluster_index,
+    );
+
+    return output_color;
+}
 
 @fragment
-fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+fn fragment(in: FragmentInput) -> FragmentOutput {
     let is_orthographic = view.projection[3].w == 1.0;
     let V = calculate_view(in.world_position, is_orthographic);
-    var tangent_V = vec3<f32>(
-        dot(V, in.world_tangent.xyz),
-        dot(V, -cross(in.world_normal, in.world_tangent.xyz) * sign(in.world_tangent.w)),
-        dot(V, in.world_normal),
-    );
-    tangent_V = normalize(tangent_V);
-    let uv_3d =  parallaxed_uv(p_material.height_depth, p_material.max_height_layers, in.uv, tangent_V);
-    let height_depth = uv_3d.z;
+    let tangent_V = tangent_space(in.world_normal, in.world_tangent, V);
+#ifdef PARALLAXED_POSITION
+    let world_per_uv = world_units_per_uv(in.world_position.xyz, in.uv);
+#endif
+#ifdef HEIGHT_MAP_UV_1
+    let uv_1 = in.uv_1;
+#else
+    let uv_1 = in.uv;
+#endif
+    let parallax = material_parallax(in.world_position, in.uv, uv_1, tangent_V);
+    let uv_3d = parallax.uv_3d;
     let uv = uv_3d.xy;
+#ifdef PARALLAXED_POSITION
+    let parallaxed_position = parallaxed_world_position(
+        in.world_position,
+        V,
+        tangent_V,
+        parallax.depth,
+        uv_3d.z,
+        world_per_uv,
+    );
+#endif
     var output_color: vec4<f32> = p_material.base_color;
 #ifdef VERTEX_COLORS
     output_color = output_color * in.color;
@@ -258,8 +283,7 @@ fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
 
         pbr_input.is_orthographic = is_orthographic;
 
-        pbr_input.N = prepare_normal_parallax(
-            p_material.flags,
+        pbr_input.N = prepare_material_normal(
             in.world_normal,
             in.is_front,
             in.world_tangent,
@@ -267,11 +291,33 @@ fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
         );
         pbr_input.V = V;
 
+#ifdef PARALLAX_SHADOWS
+        // Shadows are looked up at the parallaxed position, so that they
+        // follow the relief.
+        output_color = pbr_parallax(pbr_input, parallax, in.world_tangent, parallaxed_position);
+#else
+#ifdef SELF_SHADOWING
+        output_color = pbr_parallax(pbr_input, parallax, in.world_tangent, in.world_position);
+#else
         output_color = pbr(pbr_input);
+#endif
+#endif
         #ifdef TONEMAP_IN_SHADER
             output_color = tone_mapping(output_color);
         #endif
     }
 
-    return output_color;
+#ifdef SILHOUETTE_CLIP
+    // NOTE: we only discard at the very end, texture sampling requires
+    // uniform control flow.
+    if (outside_silhouette(uv)) {
+        discard;
+    }
+#endif
+    var out: FragmentOutput;
+    out.color = output_color;
+#ifdef WRITE_DEPTH
+    out.depth = world_position_depth(parallaxed_position);
+#endif
+    return out;
 }
@@ -0,0 +1,228 @@
+#import bevy_pbr::prepass_bindings
+#import bevy_pbr::pbr_bindings
+#import bevy_pbr::mesh_functions
+#ifdef NORMAL_PREPASS
+#import bevy_pbr::pbr_functions
+#endif
+
+#import bevy_mod_paramap::parallax_bindings
+#import bevy_mod_paramap::parallax
+#import bevy_mod_paramap::parallax_functions
+
+// Unlike bevy's prepass, we always need the uvs, normals and tangents,
+// since we need them to compute the parallaxed depth.
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
+#ifdef SKINNED
+    @location(4) joint_indices: vec4<u32>,
+    @location(5) joint_weights: vec4<f32>,
+#endif
+#ifdef HEIGHT_MAP_UV_1
+    @location(6) uv_1: vec2<f32>,
+#endif
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_tangent: vec4<f32>,
+    @location(3) world_position: vec4<f32>,
+#ifdef HEIGHT_MAP_UV_1
+    @location(4) uv_1: vec2<f32>,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+#ifdef SKINNED
+    var model = skin_model(vertex.joint_indices, vertex.joint_weights);
+    out.world_normal = skin_normals(model, vertex.normal);
+#else
+    var model = mesh.model;
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+#endif
+
+    out.world_position = mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+#ifdef DEPTH_CLAMP_ORTHO
+    out.clip_position.z = min(out.clip_position.z, 1.0);
+#endif
+
+    out.uv = vertex.uv;
+#ifdef HEIGHT_MAP_UV_1
+    out.uv_1 = vertex.uv_1;
+#endif
+    out.world_tangent = mesh_tangent_local_to_world(model, vertex.tangent);
+    return out;
+}
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_tangent: vec4<f32>,
+    @location(3) world_position: vec4<f32>,
+#ifdef HEIGHT_MAP_UV_1
+    @location(4) uv_1: vec2<f32>,
+#endif
+};
+
+// Same as `calculate_view` from `bevy_pbr::pbr_functions`, which is only
+// available with NORMAL_PREPASS.
+fn prepass_view(world_position: vec4<f32>, is_orthographic: bool) -> vec3<f32> {
+    if (is_orthographic) {
+        return normalize(vec3<f32>(view.view_proj[0].z, view.view_proj[1].z, view.view_proj[2].z));
+    } else {
+        return normalize(view.world_position.xyz - world_position.xyz);
+    }
+}
+
+// Cutoff used for the premultiplied alpha modes BLEND and ADD.
+const PREMULTIPLIED_ALPHA_CUTOFF = 0.05;
+
+// Same as `prepass_alpha_discard` from bevy's `pbr_prepass.wgsl`, but using
+// `p_material` and the parallaxed `uv`.
+//
+// Returns whether the pixel should be discarded.
+fn prepass_alpha_discard(uv: vec2<f32>) -> bool {
+    var output_color: vec4<f32> = p_material.base_color;
+    if ((p_material.flags & STANDARD_MATERIAL_FLAGS_BASE_COLOR_TEXTURE_BIT) != 0u) {
+        output_color = output_color * textureSample(p_base_color_texture, p_base_color_sampler, uv);
+    }
+    let alpha_mode = p_material.flags & STANDARD_MATERIAL_FLAGS_ALPHA_MODE_RESERVED_BITS;
+#ifdef ALPHA_MASK
+    return alpha_mode == STANDARD_MATERIAL_FLAGS_ALPHA_MODE_MASK
+        && output_color.a < p_material.alpha_cutoff;
+#else
+    let is_premultiplied = alpha_mode == STANDARD_MATERIAL_FLAGS_ALPHA_MODE_PREMULTIPLIED;
+    let is_blend = alpha_mode == STANDARD_MATERIAL_FLAGS_ALPHA_MODE_BLEND
+        || alpha_mode == STANDARD_MATERIAL_FLAGS_ALPHA_MODE_ADD;
+    return (is_blend && output_color.a < PREMULTIPLIED_ALPHA_CUTOFF)
+        || (is_premultiplied && all(output_color < vec4(PREMULTIPLIED_ALPHA_CUTOFF)));
+#endif
+}
+
+// What the prepass writes, the entry point depends on which is needed.
+struct PrepassOutput {
+    // The normal to write to the normal prepass texture.
+    normal: vec4<f32>,
+    // The depth to write to the depth buffer.
+    depth: f32,
+    // Whether to discard the pixel.
+    discard_pixel: bool,
+};
+
+fn prepass(in: FragmentInput) -> PrepassOutput {
+    var out: PrepassOutput;
+
+    let is_orthographic = view.projection[3].w == 1.0;
+    let V = prepass_view(in.world_position, is_orthographic);
+    let tangent_V = tangent_space(in.world_normal, in.world_tangent, V);
+    let world_per_uv = world_units_per_uv(in.world_position.xyz, in.uv);
+
+#ifdef HEIGHT_MAP_UV_1
+    let uv_1 = in.uv_1;
+#else
+    let uv_1 = in.uv;
+#endif
+    let parallax = material_parallax(in.world_position, in.uv, uv_1, tangent_V);
+    let uv = parallax.uv_3d.xy;
+
+#ifdef NORMAL_PREPASS
+    // NOTE: Unlit bit not set means == 0 is true, so the true case is if lit
+    if ((p_material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u) {
+        let normal = prepare_material_normal(
+            in.world_normal,
+            in.is_front,
+            in.world_tangent,
+            uv,
+        );
+        out.normal = vec4<f32>(normal * 0.5 + vec3<f32>(0.5), 1.0);
+    } else {
+        out.normal = vec4<f32>(in.world_normal * 0.5 + vec3<f32>(0.5), 1.0);
+    }
+#endif
+
+#ifdef WRITE_DEPTH
+    let parallaxed_position = parallaxed_world_position(
+        in.world_position,
+        V,
+        tangent_V,
+        parallax.depth,
+        parallax.uv_3d.z,
+        world_per_uv,
+    );
+    out.depth = world_position_depth(parallaxed_position);
+#ifdef DEPTH_CLAMP_ORTHO
+    out.depth = min(out.depth, 1.0);
+#endif
+#else
+    out.depth = in.frag_coord.z;
+#endif
+
+    out.discard_pixel = false;
+#ifdef ALPHA_MASK
+    out.discard_pixel = prepass_alpha_discard(uv);
+#endif
+#ifdef BLEND_PREMULTIPLIED_ALPHA
+    out.discard_pixel = prepass_alpha_discard(uv);
+#endif
+#ifdef BLEND_ALPHA
+    out.discard_pixel = prepass_alpha_discard(uv);
+#endif
+#ifdef SILHOUETTE_CLIP
+    out.discard_pixel = out.discard_pixel || outside_silhouette(uv);
+#endif
+    return out;
+}
+
+#ifdef NORMAL_PREPASS
+struct FragmentOutput {
+    @location(0) normal: vec4<f32>,
+#ifdef WRITE_DEPTH
+    @builtin(frag_depth) depth: f32,
+#endif
+};
+
+@fragment
+fn fragment(in: FragmentInput) -> FragmentOutput {
+    let prepass = prepass(in);
+    // NOTE: we only discard at the very end, texture sampling requires
+    // uniform control flow.
+    if (prepass.discard_pixel) {
+        discard;
+    }
+    var out: FragmentOutput;
+    out.normal = prepass.normal;
+#ifdef WRITE_DEPTH
+    out.depth = prepass.depth;
+#endif
+    return out;
+}
+#else // NORMAL_PREPASS
+#ifdef WRITE_DEPTH
+@fragment
+fn fragment(in: FragmentInput) -> @builtin(frag_depth) f32 {
+    let prepass = prepass(in);
+    if (prepass.discard_pixel) {
+        discard;
+    }
+    return prepass.depth;
+}
+#else // WRITE_DEPTH
+@fragment
+fn fragment(in: FragmentInput) {
+    let prepass = prepass(in);
+    if (prepass.discard_pixel) {
+        discard;
+    }
+}
+#endif // WRITE_DEPTH
+#endif // NORMAL_PREPASS
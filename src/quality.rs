@@ -0,0 +1,289 @@
+//! Limit the parallax mapping quality app-wide, see [`ParallaxQuality`].
+
+use bevy::{
+    pbr::RenderMaterials,
+    prelude::*,
+    render::{extract_resource::ExtractResource, render_resource::ShaderDefVal},
+};
+
+use crate::ParallaxAlgo;
+
+/// Limits on the settings of all [`ParallaxMaterial`]s, see [`ParallaxQuality`].
+///
+/// [`ParallaxMaterial`]: crate::ParallaxMaterial
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, FromReflect)]
+#[reflect(Default, Debug)]
+pub struct ParallaxQualityLimits {
+    /// Whether to run parallax mapping at all. When `false`, materials only
+    /// use normal mapping, as if `height_depth` was `0.0`, and the
+    /// `self_shadowing`, `write_depth` and `parallax_shadows` options are
+    /// disabled.
+    pub parallax_mapping: bool,
+    /// The algorithm to use instead of [`ParallaxMaterial::algorithm`],
+    /// `None` keeps the one of each material.
+    ///
+    /// [`ParallaxMaterial::algorithm`]: crate::ParallaxMaterial::algorithm
+    pub algorithm: Option<ParallaxAlgo>,
+    /// The maximum [`ParallaxMaterial::max_height_layers`], also applied
+    /// to [`DistanceLod::min_height_layers`].
+    ///
+    /// [`ParallaxMaterial::max_height_layers`]: crate::ParallaxMaterial::max_height_layers
+    /// [`DistanceLod::min_height_layers`]: crate::DistanceLod::min_height_layers
+    pub max_height_layers: f32,
+    /// The maximum [`ParallaxMaterial::refinement_steps`].
+    ///
+    /// [`ParallaxMaterial::refinement_steps`]: crate::ParallaxMaterial::refinement_steps
+    pub refinement_steps: u32,
+    /// Whether [`ParallaxMaterial::self_shadowing`] is allowed.
+    ///
+    /// [`ParallaxMaterial::self_shadowing`]: crate::ParallaxMaterial::self_shadowing
+    pub self_shadowing: bool,
+    /// Whether [`ParallaxMaterial::write_depth`] is allowed.
+    ///
+    /// Writing depth disables early depth testing, so the parallax mapping
+    /// runs for hidden fragments too.
+    ///
+    /// [`ParallaxMaterial::write_depth`]: crate::ParallaxMaterial::write_depth
+    pub write_depth: bool,
+    /// Whether [`ParallaxMaterial::parallax_shadows`] is allowed.
+    ///
+    /// This runs parallax mapping in the shadow passes, once per shadow map.
+    ///
+    /// [`ParallaxMaterial::parallax_shadows`]: crate::ParallaxMaterial::parallax_shadows
+    pub parallax_shadows: bool,
+}
+impl ParallaxQualityLimits {
+    /// No limits, materials are rendered as configured.
+    pub const NONE: Self = Self {
+        parallax_mapping: true,
+        algorithm: None,
+        max_height_layers: f32::INFINITY,
+        refinement_steps: u32::MAX,
+        self_shadowing: true,
+        write_depth: true,
+        parallax_shadows: true,
+    };
+}
+impl Default for ParallaxQualityLimits {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// The parallax mapping quality of the whole app.
+///
+/// Each [`ParallaxMaterial`] picks its own algorithm and number of layers.
+/// This resource caps them for all materials at once, for example to lower
+/// the quality on weaker platforms or from a graphics settings menu:
+///
+/// | Quality  | Algorithm                 | Max layers | Refinement steps | Self-shadowing, depth writing and parallax shadows |
+/// |----------|---------------------------|------------|------------------|----------------------------------------------------|
+/// | `Off`    | none, only normal mapping | -          | -                | no                                                 |
+/// | `Low`    | Offset limited parallax   | 4          | 0                | no                                                 |
+/// | `Medium` | the material's            | 8          | 2                | no                                                 |
+/// | `High`   | the material's            | 32         | 5                | yes                                                |
+/// | `Ultra`  | the material's            | no limit   | no limit         | yes                                                |
+///
+/// `Custom` uses the given [`ParallaxQualityLimits`]. Settings are only ever
+/// lowered: a material with 16 layers keeps 16 layers with `High`.
+///
+/// The layer count is the maximum number of steps with cone step mapping,
+/// and of iterations with quadtree displacement mapping, which needs more of
+/// them. Relief mapping only finds the surface with its refinement steps. The
+/// `Medium` caps make those three algorithms noticeably coarser, use `High`,
+/// or `Custom` with the `Medium` limits and more layers and refinement steps,
+/// if you use them on weaker platforms.
+///
+/// The limits are applied in the render world, when specializing the material
+/// pipelines: changing this resource re-specializes the pipelines of all
+/// materials, the material assets themselves are left untouched.
+/// The default is `Medium` on wasm, `Ultra` elsewhere.
+/// [`ParallaxMaterialPlugin`] inserts it if it isn't already in the app.
+///
+/// [`ParallaxMaterial`]: crate::ParallaxMaterial
+/// [`ParallaxMaterialPlugin`]: crate::ParallaxMaterialPlugin
+#[derive(Resource, ExtractResource, Debug, Clone, Copy, PartialEq, Reflect, FromReflect)]
+#[reflect(Resource, Default, Debug)]
+pub enum ParallaxQuality {
+    /// Disable parallax mapping, only use normal mapping.
+    Off,
+    /// A single height map sample with offset limited parallax mapping.
+    Low,
+    /// Few layers, no self-shadowing, depth writing or parallax shadows.
+    Medium,
+    /// Enough layers for most height maps.
+    High,
+    /// Use the material settings as is.
+    Ultra,
+    /// Use the given limits.
+    Custom(ParallaxQualityLimits),
+}
+impl Default for ParallaxQuality {
+    fn default() -> Self {
+        if cfg!(target_arch = "wasm32") {
+            Self::Medium
+        } else {
+            Self::Ultra
+        }
+    }
+}
+impl ParallaxQuality {
+    /// The limits this quality puts on [`ParallaxMaterial`] settings.
+    ///
+    /// [`ParallaxMaterial`]: crate::ParallaxMaterial
+    #[must_use]
+    pub const fn limits(self) -> ParallaxQualityLimits {
+        let limits = ParallaxQualityLimits::NONE;
+        // The extra passes of low qualities.
+        let basic = ParallaxQualityLimits {
+            self_shadowing: false,
+            write_depth: false,
+            parallax_shadows: false,
+            ..limits
+        };
+        match self {
+            Self::Off => ParallaxQualityLimits {
+                parallax_mapping: false,
+                ..basic
+            },
+            Self::Low => ParallaxQualityLimits {
+                algorithm: Some(ParallaxAlgo::OffsetLimitedParallaxMapping),
+                max_height_layers: 4.0,
+                refinement_steps: 0,
+                ..basic
+            },
+            Self::Medium => ParallaxQualityLimits {
+                max_height_layers: 8.0,
+                refinement_steps: 2,
+                ..basic
+            },
+            Self::High => ParallaxQualityLimits {
+                max_height_layers: 32.0,
+                refinement_steps: 5,
+                ..limits
+            },
+            Self::Ultra => limits,
+            Self::Custom(limits) => limits,
+        }
+    }
+}
+
+/// The [`ParallaxQualityLimits`] of the pipeline key of a material.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct QualityKey {
+    pub parallax_mapping: bool,
+    algorithm: Option<ParallaxAlgo>,
+    /// The bits of [`ParallaxQualityLimits::max_height_layers`].
+    max_height_layers: u32,
+    refinement_steps: u32,
+    pub self_shadowing: bool,
+    pub write_depth: bool,
+    pub parallax_shadows: bool,
+}
+impl From<ParallaxQualityLimits> for QualityKey {
+    fn from(limits: ParallaxQualityLimits) -> Self {
+        Self {
+            parallax_mapping: limits.parallax_mapping,
+            algorithm: limits.algorithm,
+            max_height_layers: limits.max_height_layers.to_bits(),
+            refinement_steps: limits.refinement_steps,
+            self_shadowing: limits.self_shadowing,
+            write_depth: limits.write_depth,
+            parallax_shadows: limits.parallax_shadows,
+        }
+    }
+}
+impl Default for QualityKey {
+    fn default() -> Self {
+        ParallaxQualityLimits::NONE.into()
+    }
+}
+impl QualityKey {
+    /// The algorithm to use instead of a material's `algorithm`.
+    pub fn algorithm(self, algorithm: ParallaxAlgo, has_cone_map: bool) -> ParallaxAlgo {
+        self.algorithm
+            .unwrap_or(algorithm)
+            .with_cone_map(has_cone_map)
+    }
+
+    /// The shader defs capping the layer count and refinement steps in the
+    /// `quality_layer_count` and `quality_refinement_steps` shader functions.
+    pub fn shader_defs(self) -> Vec<ShaderDefVal> {
+        let mut defs = Vec::new();
+        if f32::from_bits(self.max_height_layers).is_finite() {
+            defs.push(ShaderDefVal::UInt(
+                "MAX_HEIGHT_LAYERS_CAP".into(),
+                self.max_height_layers,
+            ));
+        }
+        if self.refinement_steps != u32::MAX {
+            defs.push(ShaderDefVal::UInt(
+                "REFINEMENT_STEPS_CAP".into(),
+                self.refinement_steps,
+            ));
+        }
+        defs
+    }
+}
+
+/// A material pipeline key holding [`ParallaxQuality`] limits.
+pub trait QualityLimited {
+    /// The limits of this key.
+    fn quality_mut(&mut self) -> &mut QualityKey;
+}
+
+/// Set the current [`ParallaxQuality`] limits on the keys of the prepared `M`
+/// materials.
+///
+/// The material keys are built without access to the world, this runs right
+/// after the `M` materials are prepared, so that their pipelines are
+/// specialized with the current limits.
+#[allow(clippy::needless_pass_by_value)]
+pub fn limit_prepared_materials<M: Material>(
+    quality: Res<ParallaxQuality>,
+    mut materials: ResMut<RenderMaterials<M>>,
+) where
+    M::Data: QualityLimited,
+{
+    let limits = quality.limits().into();
+    for material in materials.0.values_mut() {
+        let quality = material.key.quality_mut();
+        if *quality != limits {
+            *quality = limits;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_algorithm_and_keeps_cone_fallback() {
+        let low = QualityKey::from(ParallaxQuality::Low.limits());
+        let algorithm = low.algorithm(ParallaxAlgo::ReliefMapping, false);
+        assert_eq!(algorithm, ParallaxAlgo::OffsetLimitedParallaxMapping);
+
+        let ultra = QualityKey::from(ParallaxQuality::Ultra.limits());
+        let algorithm = ultra.algorithm(ParallaxAlgo::ConeStepMapping, false);
+        assert_eq!(algorithm, ParallaxAlgo::ParallaxOcclusionMapping);
+        let algorithm = ultra.algorithm(ParallaxAlgo::ConeStepMapping, true);
+        assert_eq!(algorithm, ParallaxAlgo::ConeStepMapping);
+    }
+
+    #[test]
+    fn caps_only_limited_settings() {
+        let ultra = QualityKey::from(ParallaxQuality::Ultra.limits());
+        assert!(ultra.shader_defs().is_empty());
+
+        let medium = QualityKey::from(ParallaxQuality::Medium.limits());
+        let defs = medium.shader_defs();
+        assert!(defs.contains(&ShaderDefVal::UInt(
+            "MAX_HEIGHT_LAYERS_CAP".into(),
+            8.0_f32.to_bits()
+        )));
+        assert!(defs.contains(&ShaderDefVal::UInt("REFINEMENT_STEPS_CAP".into(), 2)));
+    }
+}
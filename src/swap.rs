@@ -0,0 +1,193 @@
+//! Replace [`StandardMaterial`]s with [`ParallaxMaterial`]s, see [`ParallaxRegistry`].
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::ParallaxMaterial;
+
+/// The [`ParallaxMaterial`] to use in place of a [`StandardMaterial`].
+#[derive(Debug)]
+enum Swap {
+    /// The [`StandardMaterial`] isn't loaded yet, its fields will replace
+    /// the ones of this material.
+    Pending(Box<ParallaxMaterial>),
+    /// The [`ParallaxMaterial`] was added to the [`Assets`].
+    Done(Handle<ParallaxMaterial>),
+}
+
+/// [`StandardMaterial`]s to replace with [`ParallaxMaterial`]s.
+///
+/// Entities with a registered `Handle<StandardMaterial>` get it replaced by a
+/// `Handle<ParallaxMaterial>`, combining the [`StandardMaterial`] with the
+/// registered height map and parallax settings.
+///
+/// This is useful for scenes loaded from glTF files, which only use
+/// [`StandardMaterial`]s. Register the material using its asset path, for
+/// example `asset_server.load("scene.gltf#Material0")`, before the scene
+/// spawns.
+///
+/// All entities using the same [`StandardMaterial`] share the same
+/// [`ParallaxMaterial`].
+#[derive(Resource, Default, Debug)]
+pub struct ParallaxRegistry {
+    swaps: HashMap<Handle<StandardMaterial>, Swap>,
+}
+impl ParallaxRegistry {
+    /// Replace `standard` by `parallax` on all entities using it.
+    ///
+    /// The [`StandardMaterial`] fields of `parallax` are overwritten by
+    /// the ones of `standard`, see [`ParallaxMaterial::with_standard`].
+    pub fn register(&mut self, standard: Handle<StandardMaterial>, parallax: ParallaxMaterial) {
+        self.swaps
+            .insert(standard, Swap::Pending(Box::new(parallax)));
+    }
+
+    /// Replace `standard` by a [`ParallaxMaterial`] using `height_map`,
+    /// with the default parallax settings.
+    pub fn register_height_map(
+        &mut self,
+        standard: Handle<StandardMaterial>,
+        height_map: Handle<Image>,
+    ) {
+        let parallax = ParallaxMaterial {
+            height_map,
+            ..default()
+        };
+        self.register(standard, parallax);
+    }
+
+    /// Whether `standard` is registered.
+    #[must_use]
+    pub fn contains(&self, standard: &Handle<StandardMaterial>) -> bool {
+        self.swaps.contains_key(standard)
+    }
+
+    /// The [`ParallaxMaterial`] replacing `standard`, if it was already created.
+    #[must_use]
+    pub fn get(&self, standard: &Handle<StandardMaterial>) -> Option<&Handle<ParallaxMaterial>> {
+        match self.swaps.get(standard)? {
+            Swap::Done(handle) => Some(handle),
+            Swap::Pending(_) => None,
+        }
+    }
+}
+
+/// Replace the registered `Handle<StandardMaterial>`s by `Handle<ParallaxMaterial>`,
+/// see [`ParallaxRegistry`].
+///
+/// Only newly added handles are checked, and the ones waiting for their
+/// [`StandardMaterial`] to load or to be registered. When a replaced
+/// [`StandardMaterial`] is modified, its [`ParallaxMaterial`] is updated with
+/// [`ParallaxMaterial::with_standard`].
+#[allow(clippy::needless_pass_by_value, clippy::type_complexity)]
+pub fn swap_standard_materials(
+    mut commands: Commands,
+    mut registry: ResMut<ParallaxRegistry>,
+    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut parallax_materials: ResMut<Assets<ParallaxMaterial>>,
+    added: Query<(Entity, &Handle<StandardMaterial>), Added<Handle<StandardMaterial>>>,
+    all: Query<(Entity, &Handle<StandardMaterial>)>,
+) {
+    let mut loaded = HashSet::new();
+    let mut modified = Vec::new();
+    for event in &mut events {
+        match event {
+            AssetEvent::Created { handle } => {
+                loaded.insert(handle);
+            }
+            AssetEvent::Modified { handle } => modified.push(handle),
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+    if registry.swaps.is_empty() {
+        return;
+    }
+    // Keep the swapped materials in sync with their source, for example when
+    // hot-reloading it.
+    for standard in modified {
+        let Some(Swap::Done(parallax)) = registry.swaps.get(standard) else {
+            continue;
+        };
+        let (Some(standard_material), Some(parallax_material)) = (
+            standard_materials.get(standard),
+            parallax_materials.get_mut(parallax),
+        ) else {
+            continue;
+        };
+        *parallax_material = parallax_material
+            .clone()
+            .with_standard(standard_material.clone());
+    }
+    // Entities spawned before their material was registered or loaded.
+    let registered = registry.is_changed();
+    let waiting = (registered || !loaded.is_empty()).then(|| {
+        all.iter()
+            .filter(move |(_, standard)| registered || loaded.contains(standard))
+    });
+    for (entity, standard) in added.iter().chain(waiting.into_iter().flatten()) {
+        let parallax = match registry.swaps.get(standard) {
+            None => continue,
+            Some(Swap::Done(parallax)) => parallax.clone(),
+            Some(Swap::Pending(parallax)) => {
+                let Some(standard_material) = standard_materials.get(standard) else {
+                    continue;
+                };
+                let parallax = (**parallax)
+                    .clone()
+                    .with_standard(standard_material.clone());
+                let parallax = parallax_materials.add(parallax);
+                let done = Swap::Done(parallax.clone());
+                registry.swaps.insert(standard.clone(), done);
+                parallax
+            }
+        };
+        commands
+            .entity(entity)
+            .remove::<Handle<StandardMaterial>>()
+            .insert(parallax);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::AssetPlugin;
+
+    use super::*;
+
+    #[test]
+    fn modified_standard_material_updates_its_swap() {
+        let mut app = App::new();
+        app.add_plugin(AssetPlugin::default())
+            .add_asset::<StandardMaterial>()
+            .add_asset::<ParallaxMaterial>()
+            .init_resource::<ParallaxRegistry>()
+            .add_system(swap_standard_materials);
+        let standard = app
+            .world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(Color::RED.into());
+        app.world
+            .resource_mut::<ParallaxRegistry>()
+            .register_height_map(standard.clone(), Handle::default());
+        let entity = app.world.spawn(standard.clone()).id();
+        app.update();
+
+        let parallax = app.world.get::<Handle<ParallaxMaterial>>(entity).cloned();
+        let parallax = parallax.expect("the material should be swapped");
+        let base_color = |app: &App| {
+            let materials = app.world.resource::<Assets<ParallaxMaterial>>();
+            materials.get(&parallax).unwrap().base_color
+        };
+        assert_eq!(base_color(&app), Color::RED);
+
+        let mut standard_materials = app.world.resource_mut::<Assets<StandardMaterial>>();
+        standard_materials.get_mut(&standard).unwrap().base_color = Color::BLUE;
+        // NOTE: The `AssetEvent::Modified` is sent at the end of the first update.
+        app.update();
+        app.update();
+        assert_eq!(base_color(&app), Color::BLUE);
+    }
+}
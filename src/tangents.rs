@@ -0,0 +1,73 @@
+//! Generate missing mesh tangents, see [`generate_missing_tangents`].
+
+use bevy::{asset::LoadState, prelude::*, utils::HashSet};
+
+use crate::ParallaxMaterial;
+
+/// Generate tangents for meshes used with a [`ParallaxMaterial`] that lack them.
+///
+/// Parallax mapping needs tangents, without them the surface renders black
+/// or seems to swim. The tangents are generated with [`Mesh::generate_tangents`],
+/// if it fails, for example because the mesh has no UVs, a warning names
+/// the entity using the mesh.
+///
+/// Meshes are checked when an entity gets a `Handle<ParallaxMaterial>` or its
+/// `Handle<Mesh>` changes, once they are loaded, and when they are modified,
+/// for example when hot-reloading them. Meshes that failed to load are skipped.
+#[allow(clippy::type_complexity, clippy::needless_pass_by_value)]
+pub fn generate_missing_tangents(
+    mut pending: Local<Vec<(Entity, Handle<Mesh>)>>,
+    mut events: EventReader<AssetEvent<Mesh>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    parallax_meshes: Query<(Entity, &Handle<Mesh>), With<Handle<ParallaxMaterial>>>,
+    query: Query<
+        (Entity, &Handle<Mesh>),
+        (
+            With<Handle<ParallaxMaterial>>,
+            Or<(Changed<Handle<Mesh>>, Added<Handle<ParallaxMaterial>>)>,
+        ),
+    >,
+) {
+    let modified: HashSet<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle),
+            AssetEvent::Created { .. } | AssetEvent::Removed { .. } => None,
+        })
+        .collect();
+    if !modified.is_empty() {
+        let modified = parallax_meshes
+            .iter()
+            .filter(|(_, mesh)| modified.contains(mesh));
+        pending.extend(modified.map(|(entity, mesh)| (entity, mesh.clone_weak())));
+    }
+    pending.extend(
+        query
+            .iter()
+            .map(|(entity, mesh)| (entity, mesh.clone_weak())),
+    );
+    pending.retain(|(entity, handle)| {
+        let has_tangents = match meshes.get(handle) {
+            None => {
+                // Not loaded yet, check again next frame, unless it never will be.
+                let state = asset_server.get_load_state(handle);
+                return !matches!(state, LoadState::Failed | LoadState::Unloaded);
+            }
+            Some(mesh) => mesh.attribute(Mesh::ATTRIBUTE_TANGENT).is_some(),
+        };
+        if has_tangents {
+            return false;
+        }
+        let Some(mesh) = meshes.get_mut(handle) else {
+            return false;
+        };
+        if let Err(err) = mesh.generate_tangents() {
+            warn!(
+                "{entity:?} uses a ParallaxMaterial, but its mesh has no tangents and \
+                generating them failed: {err}. Parallax mapping won't work on it."
+            );
+        }
+        false
+    });
+}